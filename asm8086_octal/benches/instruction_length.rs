@@ -0,0 +1,69 @@
+//! Throughput of finding instruction boundaries against decoding every instruction.
+//! Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use asm8086_octal::decoder::{self, Mode};
+
+const IMAGE_SIZE: usize = 8 << 20;
+const ROUNDS: usize = 5;
+
+/// The course listings repeated until the image is `IMAGE_SIZE` long, cut on an instruction.
+fn image() -> Vec<u8> {
+    let listings: [&[u8]; 3] = [
+        include_bytes!("../listing_0039_more_movs.bin"),
+        include_bytes!("../listing_0040_challenge_movs.bin"),
+        include_bytes!("../listing_0041_add_sub_cmp_jnz.bin"),
+    ];
+    let mut image = Vec::with_capacity(IMAGE_SIZE);
+    while image.len() < IMAGE_SIZE {
+        for listing in listings {
+            image.extend_from_slice(listing);
+        }
+    }
+    image
+}
+
+fn boundaries(image: &[u8]) -> usize {
+    let mut offset = 0;
+    let mut count = 0;
+    while offset < image.len() {
+        match decoder::instruction_length(&image[offset..]) {
+            Ok(length) => offset += length,
+            Err(_) => break,
+        }
+        count += 1;
+    }
+    count
+}
+
+fn decode(image: &[u8]) -> usize {
+    decoder::Decoder::new(image, Mode::default())
+        .take_while(Result::is_ok)
+        .count()
+}
+
+/// Best of `ROUNDS` runs in megabytes per second.
+fn throughput(name: &str, image: &[u8], run: fn(&[u8]) -> usize) {
+    let mut best = Duration::MAX;
+    let mut count = 0;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        count = black_box(run(black_box(image)));
+        best = best.min(start.elapsed());
+    }
+    let megabytes = image.len() as f64 / (1 << 20) as f64;
+    println!(
+        "{:<20} {:>8.1} MB/s ({} instructions)",
+        name,
+        megabytes / best.as_secs_f64(),
+        count
+    );
+}
+
+fn main() {
+    let image = image();
+    throughput("instruction_length", &image, boundaries);
+    throughput("full decode", &image, decode);
+}
@@ -0,0 +1,349 @@
+//! Turns the one byte opcode lines of the opcode summary in `octal_x86.txt`
+//! into the 256 entry template table behind `decoder::opcode_to_instruction`.
+//!
+//! A line like `* 151 xrm Dw  imul Rw, Ew, Dw` gives the cpu marker, the octal
+//! opcode with its P / r / s / +CC digits, the encoding and the mnemonic with its
+//! operands. Group opcodes (x0m, xPm), the 0o017 escape and the x87 section keep
+//! their hand written tables, prefixes are handled by the decode loop.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const REFERENCE: &str = "../octal_x86.txt";
+const SUMMARY_START: &str = "(5) The Opcode Summary";
+const SUMMARY_END: &str = "CO-PROCESSOR ESCAPE SEQUENCE";
+
+/// Immediates that are counts, ports or interrupt numbers and print unsigned.
+const UNSIGNED_IMMEDIATES: [&str; 8] =
+    ["int", "in", "out", "ret", "ret far", "enter", "aam", "aad"];
+
+/// Mnemonics of the reference that are spelled differently in `Asm8086`.
+const ALIASES: [(&str, &str); 3] = [("xlatb", "Xlat"), ("ret far", "Retf"), ("int 3", "Int3")];
+
+struct Line<'a> {
+    cpu: &'static str,
+    /// r digit of an `x0m` style encoding, the member of a group opcode.
+    member: Option<char>,
+    opcode: &'a str,
+    encoding: Vec<&'a str>,
+    mnemonic: String,
+    operands: Vec<String>,
+    excluded_r: Option<u8>,
+}
+
+fn cpu_of(marker: &str) -> Option<&'static str> {
+    match marker {
+        "*" => Some("Cpu::I80186"),
+        "$" => Some("Cpu::I80286"),
+        "#" => Some("Cpu::I80386"),
+        _ => None,
+    }
+}
+
+fn is_opcode(token: &str) -> bool {
+    let digits = token.strip_suffix("+CC").unwrap_or(token);
+    digits.len() == 3
+        && digits.starts_with(['0', '1', '2', '3'])
+        && digits
+            .chars()
+            .all(|c| matches!(c, '0'..='7' | 'P' | 'r' | 's'))
+}
+
+fn is_encoding(token: &str) -> bool {
+    let is_mod_rm = token.len() == 3 && token.starts_with('x') && token.ends_with('m');
+    let is_literal = token.len() == 3 && token.chars().all(|c| matches!(c, '0'..='7'));
+    is_mod_rm || is_literal || matches!(token, "Db" | "Dw" | "Dc" | "Cb" | "Cw" | "Af")
+}
+
+fn parse_line(line: &str) -> Option<Line<'_>> {
+    let mut tokens = line.split_whitespace().peekable();
+    let cpu = match cpu_of(tokens.peek()?) {
+        Some(cpu) => {
+            tokens.next();
+            cpu
+        }
+        // the 486 additions are all behind the escape byte
+        None if *tokens.peek()? == "@" => return None,
+        None => "Cpu::I8086",
+    };
+    let opcode = tokens.next().filter(|token| is_opcode(token))?;
+    if opcode == "017" && tokens.peek().is_some_and(|token| is_opcode(token)) {
+        return None;
+    }
+    let mut encoding = Vec::new();
+    while let Some(token) = tokens.next_if(|token| is_encoding(token)) {
+        encoding.push(token);
+    }
+    // groups whose operation is the P digit stay hand written
+    if encoding.contains(&"xPm") {
+        return None;
+    }
+    let text = tokens.collect::<Vec<_>>().join(" ");
+    let excluded_r = text
+        .split_once("(r != ")
+        .map(|(_, rest)| rest[..1].parse().expect("register digit"));
+    // later spellings after `/`, notes in parentheses and the basic line numbers are dropped
+    let text = text.split(['/', '(', '\'']).next()?.trim();
+    let (name, operands) = text.split_once(' ').unwrap_or((text, ""));
+    let (mnemonic, operands) = match operands.split_once(' ') {
+        Some(("far", operands)) => (format!("{} far", name), operands),
+        _ if operands == "far" => (format!("{} far", name), ""),
+        _ => (name.to_string(), operands),
+    };
+    let mut operands: Vec<String> = operands
+        .split(',')
+        .map(|operand| operand.trim().to_string())
+        .filter(|operand| !operand.is_empty())
+        .collect();
+    let mut mnemonic = mnemonic;
+    if operands == ["3"] {
+        mnemonic = format!("{} 3", mnemonic);
+        operands.clear();
+    }
+    if mnemonic.ends_with(':') || matches!(mnemonic.as_str(), "lock" | "repne" | "repe") {
+        return None;
+    }
+    let member = encoding
+        .iter()
+        .find(|token| token.starts_with('x') && !matches!(**token, "xrm" | "xsm"))
+        .and_then(|token| token.chars().nth(1));
+    Some(Line {
+        cpu,
+        member,
+        opcode,
+        encoding,
+        mnemonic,
+        operands,
+        excluded_r,
+    })
+}
+
+fn variant(mnemonic: &str) -> String {
+    if let Some((_, alias)) = ALIASES.iter().find(|(name, _)| *name == mnemonic) {
+        return format!("Asm8086::{}", alias);
+    }
+    let name = mnemonic.strip_suffix(" far").unwrap_or(mnemonic);
+    let mut chars = name.chars();
+    let first = chars.next().expect("empty mnemonic").to_ascii_uppercase();
+    format!("Asm8086::{}{}", first, chars.as_str())
+}
+
+fn register(name: &str) -> Option<String> {
+    const BYTE: [&str; 8] = ["AL", "CL", "DL", "BL", "AH", "CH", "DH", "BH"];
+    const WORD: [&str; 8] = ["AX", "CX", "DX", "BX", "SP", "BP", "SI", "DI"];
+    if BYTE.contains(&name) {
+        Some(format!("Rb(Address::ByteRegister(ByteRegister::{}))", name))
+    } else if WORD.contains(&name) {
+        Some(format!("Rw(Address::WordRegister(WordRegister::{}))", name))
+    } else {
+        None
+    }
+}
+
+/// Operand template for one operand of the reference, `digit` is the r or s of the opcode.
+fn operand(line: &Line, operand: &str, digit: u8) -> String {
+    let has_mod_rm = line.encoding.iter().any(|token| token.starts_with('x'));
+    let immediate = if UNSIGNED_IMMEDIATES.contains(&line.mnemonic.as_str()) {
+        "Du"
+    } else {
+        "D"
+    };
+    match operand {
+        "Eb" => "Eb(Address::ByteRegisterUnread)".into(),
+        // selectors and near pointers are words, far pointers keep their own template
+        "Ew" | "Es" | "En" => "Ew(Address::WordRegisterUnread)".into(),
+        "Ef" => "Ef(Address::WordRegisterUnread)".into(),
+        // the pair of bounds of `bound`, only ever memory
+        "Ed" => "Em(Address::PointerUnread)".into(),
+        "Rb" if has_mod_rm => "Rb(Address::ByteRegisterUnread)".into(),
+        "Rw" if has_mod_rm => "Rw(Address::WordRegisterUnread)".into(),
+        "SR" if has_mod_rm => "SR(Address::SegmentRegisterUnread)".into(),
+        "Rb" => format!("Rb(Address::ByteRegister(ByteRegister::from_r({})))", digit),
+        "Rw" => format!("Rw(Address::WordRegister(WordRegister::from_r({})))", digit),
+        "SR" => format!(
+            "SR(Address::SegmentRegister(SegmentRegister::from_s({})))",
+            digit
+        ),
+        "Db" => format!("{}(Disp::D8Unread)", immediate),
+        "Dw" => format!("{}(Disp::D16Unread)", immediate),
+        "Dc" => "D(Disp::DcUnread)".into(),
+        "Cb" => "C(Disp::D8Unread)".into(),
+        "Cw" => "C(Disp::D16Unread)".into(),
+        "Af" => "Af(Disp::D16Unread, Disp::D16Unread)".into(),
+        "[Dw]" => {
+            let word = line.operands.iter().any(|other| other == "AX");
+            let size = if word { "Ew" } else { "Eb" };
+            format!(
+                "{}(Address::Pointer(Pointer::direct(Disp::D16Unread)))",
+                size
+            )
+        }
+        _ => register(operand)
+            .unwrap_or_else(|| panic!("unknown operand '{}' of '{}'", operand, line.opcode)),
+    }
+}
+
+/// Parts of the immediate data an instruction reads after its mod rm byte, in order.
+fn immediate(line: &Line) -> String {
+    let unsigned = UNSIGNED_IMMEDIATES.contains(&line.mnemonic.as_str());
+    let mut parts: Vec<&str> = Vec::new();
+    for operand in line.operands.iter() {
+        match operand.as_str() {
+            "Db" | "Dc" | "Cb" => parts.push("Byte"),
+            // counts and ports stay words, the other immediates follow the operand size
+            "Dw" if unsigned => parts.push("FixedWord"),
+            "Dw" | "Cw" => parts.push("Word"),
+            "Af" => parts.extend(["Word", "FixedWord"]),
+            "[Dw]" => parts.push("Address"),
+            _ => {}
+        }
+    }
+    if line
+        .encoding
+        .iter()
+        .any(|token| token.starts_with(char::is_numeric))
+    {
+        parts.push("Byte");
+    }
+    let parts: Vec<String> = parts
+        .into_iter()
+        .map(|part| format!("Immediate::{}", part))
+        .collect();
+    format!("&[{}]", parts.join(", "))
+}
+
+fn operands(operands: Vec<String>) -> String {
+    let operands: Vec<String> = operands
+        .into_iter()
+        .map(|operand| format!("Operand::{}", operand))
+        .collect();
+    match operands.as_slice() {
+        [] => "Operands::None".into(),
+        [first] => format!("Operands::One({})", first),
+        [first, second] => format!("Operands::Two({}, {})", first, second),
+        [first, second, third] => format!("Operands::Three({}, {}, {})", first, second, third),
+        _ => panic!("too many operands"),
+    }
+}
+
+/// Every opcode byte the line stands for together with its template.
+fn expand(line: &Line, p_names: &BTreeMap<u8, String>) -> Vec<(u8, String)> {
+    let (digits, cc) = match line.opcode.strip_suffix("+CC") {
+        Some(digits) => (digits, true),
+        None => (line.opcode, false),
+    };
+    let variable = digits.find(['P', 'r', 's']);
+    let values: Vec<u8> = match variable.map(|at| &digits[at..=at]) {
+        None if cc => (0..16).collect(),
+        None => vec![0],
+        // 0s6 and up are the segment override prefixes, pop cs stays on the 8086
+        Some("s") => (0..4).collect(),
+        Some(_) => (0..8).filter(|r| Some(*r) != line.excluded_r).collect(),
+    };
+    let base = u8::from_str_radix(&digits.replace(['P', 'r', 's'], "0"), 8).expect("octal opcode");
+    let shift = variable.map_or(0, |at| 3 * (2 - at as u32));
+    values
+        .into_iter()
+        .map(|value| {
+            let opcode = base + (value << shift);
+            let op = match line.mnemonic.as_str() {
+                "Op" => variant(&p_names[&value]),
+                "jcc" => format!("Asm8086::from_cc({})", value),
+                _ => variant(&line.mnemonic),
+            };
+            let mut templates: Vec<String> = line
+                .operands
+                .iter()
+                .map(|name| operand(line, name, value))
+                .collect();
+            // the base of aam and aad is a byte after the opcode
+            if line
+                .encoding
+                .iter()
+                .any(|token| token.starts_with(char::is_numeric))
+            {
+                templates.push("Du(Disp::D8Unread)".into());
+            }
+            let member = match line.member {
+                Some(r) => format!("Some({})", r),
+                None => "None".into(),
+            };
+            let mod_rm = line.encoding.iter().any(|token| token.starts_with('x'));
+            let template = format!(
+                "Some(OpcodeTemplate {{ op: {}, operands: {}, cpu: {}, member: {}, \
+                 mod_rm: {}, immediate: {} }})",
+                op,
+                operands(templates),
+                line.cpu,
+                member,
+                mod_rm,
+                immediate(line)
+            );
+            (opcode, template)
+        })
+        .collect()
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", REFERENCE);
+    let reference = fs::read_to_string(REFERENCE).expect("reading octal_x86.txt");
+    let summary = reference
+        .split_once(SUMMARY_START)
+        .and_then(|(_, rest)| rest.split_once(SUMMARY_END))
+        .map(|(summary, _)| summary)
+        .expect("opcode summary of octal_x86.txt");
+
+    // keyed by the opcode and the group member, more than one member makes a group
+    let mut table: BTreeMap<(u8, Option<char>), String> = BTreeMap::new();
+    let mut p_names: BTreeMap<u8, String> = BTreeMap::new();
+    let mut in_p_table = false;
+    for text in summary.lines() {
+        // `P Op  Description` starts the names an Op with a P digit stands for
+        if text.starts_with("P Op") {
+            p_names.clear();
+            in_p_table = true;
+            continue;
+        }
+        if in_p_table {
+            let mut words = text.split_whitespace();
+            match (
+                words.next().and_then(|p| p.parse::<u8>().ok()),
+                words.next(),
+            ) {
+                (Some(p), Some(name)) => {
+                    p_names.entry(p).or_insert_with(|| name.to_lowercase());
+                    continue;
+                }
+                _ => in_p_table = false,
+            }
+        }
+        let Some(line) = parse_line(text) else {
+            continue;
+        };
+        for (opcode, template) in expand(&line, &p_names) {
+            match table.get(&(opcode, line.member)) {
+                Some(known) if *known != template => {
+                    panic!("opcode {:#o} is listed twice differently", opcode)
+                }
+                _ => {
+                    table.insert((opcode, line.member), template);
+                }
+            }
+        }
+    }
+
+    let mut source = String::from("[\n");
+    for opcode in 0..=255u8 {
+        let mut templates = table.range((opcode, None)..=(opcode, Some(char::MAX)));
+        let template = match (templates.next(), templates.next()) {
+            (Some((_, template)), None) => template.as_str(),
+            _ => "None",
+        };
+        source.push_str(&format!("    /* {:#05o} */ {},\n", opcode, template));
+    }
+    source.push_str("]\n");
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR");
+    fs::write(Path::new(&out_dir).join("opcode_table.rs"), source).expect("writing opcode table");
+}
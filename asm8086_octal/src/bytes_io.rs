@@ -14,17 +14,44 @@ pub fn read_bytes(filename: &str) -> io::Result<Vec<u8>> {
 }
 
 pub fn read_bytes_cli() -> Result<Vec<u8>, String> {
-    let args: Vec<String> = std::env::args().collect();
-    match args.len() {
-        1 => Err("Have not found binary to decompile".into()),
-        _ => {
-            let filename = args[1].borrow();
+    // options are written as --name=value and never taken for the file name
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+    match args.first() {
+        None => Err("Have not found binary to decompile".into()),
+        Some(filename) => {
+            let filename: &str = filename.borrow();
             read_bytes(filename).map_err(|_| format!("Unable to read file '{}'", filename))
         }
     }
 }
 
 
+/// Value of a `--name=value` command line option.
+pub fn option_cli(name: &str) -> Option<String> {
+    let prefix = format!("--{}=", name);
+    std::env::args().find_map(|arg| arg.strip_prefix(&prefix).map(String::from))
+}
+
+/// Whether the `--name` switch is on the command line.
+pub fn flag_cli(name: &str) -> bool {
+    let flag = format!("--{}", name);
+    std::env::args().any(|arg| arg == flag)
+}
+
+/// Number written in decimal or with a `0x`, `0o` or `0b` prefix.
+pub fn parse_number(text: &str) -> Result<u64, String> {
+    let (digits, radix) = match text.get(..2) {
+        Some("0x") => (&text[2..], 16),
+        Some("0o") => (&text[2..], 8),
+        Some("0b") => (&text[2..], 2),
+        _ => (text, 10),
+    };
+    u64::from_str_radix(digits, radix).map_err(|_| format!("Invalid number '{}'", text))
+}
+
 pub fn format_bytes(bytes: &[u8], start: usize, end: usize) -> String {
     let mut result = String::new();
     for byte in bytes[start..end].iter() {
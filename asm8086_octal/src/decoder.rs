@@ -0,0 +1,2303 @@
+use std::fmt::Display;
+
+use crate::instruction::{
+    Address, Asm8086, ByteRegister, DecodedInstruction, Disp, DwordRegister, Operand, Operands,
+    Pointer, Prefixes, QwordRegister, Repeat, SegmentRegister, WordRegister,
+};
+use crate::opcode_table::OPCODE_TABLE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The input ends in the middle of the instruction.
+    Truncated,
+    /// The opcode is not part of the instruction set of the decoding mode.
+    UnknownOpcode,
+    /// The mod rm byte names a register where only memory is allowed or a
+    /// segment register that does not exist.
+    InvalidModRm,
+    /// Prefixes keep the instruction going past the 15 bytes the cpu accepts.
+    TooLong,
+}
+
+impl Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            DecodeErrorKind::Truncated => "truncated instruction",
+            DecodeErrorKind::UnknownOpcode => "unknown opcode",
+            DecodeErrorKind::InvalidModRm => "invalid mod rm byte",
+            DecodeErrorKind::TooLong => "instruction longer than 15 bytes",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Why the instruction at `offset` could not be decoded and the bytes of it read so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl DecodeError {
+    fn new(kind: DecodeErrorKind, bytes: &[u8], offset: usize, end_ptr: usize) -> Self {
+        DecodeError {
+            kind,
+            offset,
+            bytes: bytes[offset..end_ptr.min(bytes.len())].to_vec(),
+        }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {} (", self.kind, self.offset)?;
+        for byte in self.bytes.iter() {
+            write!(f, "[{:#o}]", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Processor whose instruction set the byte stream is decoded against.
+/// Opcodes added by a later model decode as `Unknown` on the earlier ones.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cpu {
+    #[default]
+    I8086,
+    I80186,
+    I80286,
+    I80386,
+}
+
+impl std::str::FromStr for Cpu {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "8086" | "8088" => Ok(Cpu::I8086),
+            "186" | "80186" | "188" | "80188" => Ok(Cpu::I80186),
+            "286" | "80286" => Ok(Cpu::I80286),
+            "386" | "80386" => Ok(Cpu::I80386),
+            _ => Err(format!("Unknown cpu '{}'", text)),
+        }
+    }
+}
+
+/// Default word and address size of the code segment, real mode code is 16-bit.
+/// Also used for the operand and address size in effect for a single instruction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Bits {
+    #[default]
+    Sixteen,
+    ThirtyTwo,
+    SixtyFour,
+}
+
+impl std::str::FromStr for Bits {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "16" => Ok(Bits::Sixteen),
+            "32" => Ok(Bits::ThirtyTwo),
+            "64" => Ok(Bits::SixtyFour),
+            _ => Err(format!("Unknown bits '{}'", text)),
+        }
+    }
+}
+
+/// Processor and default sizes the byte stream is decoded with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub cpu: Cpu,
+    pub bits: Bits,
+}
+
+impl Mode {
+    /// 32 and 64-bit code needs at least a 386, so `cpu` is raised to it when it is older.
+    /// 64-bit mode covers the common integer subset: REX, the 386 set and RIP addressing.
+    pub fn new(cpu: Cpu, bits: Bits) -> Self {
+        let cpu = match bits {
+            Bits::Sixteen => cpu,
+            Bits::ThirtyTwo | Bits::SixtyFour => cpu.max(Cpu::I80386),
+        };
+        Mode { cpu, bits }
+    }
+}
+
+impl From<Cpu> for Mode {
+    fn from(cpu: Cpu) -> Self {
+        Mode::new(cpu, Bits::Sixteen)
+    }
+}
+
+/// Opcodes dropped by 64-bit mode, 0o100-0o117 turned into the REX prefix.
+fn is_invalid_in_long_mode(opcode_byte: u8) -> bool {
+    matches!(
+        opcode_byte,
+        0o006
+            | 0o007
+            | 0o016
+            | 0o026
+            | 0o027
+            | 0o036
+            | 0o037
+            | 0o047
+            | 0o057
+            | 0o067
+            | 0o077
+            | 0o140..=0o142 | 0o202 | 0o232 | 0o304 | 0o305 | 0o316 | 0o324 | 0o325 | 0o352
+    )
+}
+
+/// Opcodes outside the groups, looked up in the table generated from octal_x86.txt
+/// apart from what 64-bit mode changes.
+fn opcode_to_instruction(opcode_byte: u8, mode: Mode) -> (Asm8086, Operands) {
+    use Operand::*;
+    let long = mode.bits == Bits::SixtyFour;
+    match opcode_byte {
+        _ if long && is_invalid_in_long_mode(opcode_byte) => (Asm8086::Unknown, Operands::None),
+        // sign extend double word 0o143 xrm replaces arpl in 64-bit mode
+        0o143 if long => (
+            Asm8086::Movsxd,
+            Operands::Two(
+                Rw(Address::WordRegisterUnread),
+                Ed(Address::DwordRegisterUnread),
+            ),
+        ),
+        _ => match OPCODE_TABLE[opcode_byte as usize] {
+            Some(template) if mode.cpu >= template.cpu => (template.op, template.operands),
+            _ => (Asm8086::Unknown, Operands::None),
+        },
+    }
+}
+
+fn is_group_opcode(opcode_byte: u8, cpu: Cpu) -> bool {
+    match opcode_byte {
+        0o300 | 0o301 => cpu >= Cpu::I80186,
+        _ => matches!(
+            opcode_byte,
+            0o200..=0o203 | 0o320..=0o323 | 0o330..=0o337 | 0o366 | 0o367 | 0o376 | 0o377
+        ),
+    }
+}
+
+/// Instructions whose operation is selected by the r digit of the xrm byte.
+fn group_to_instruction(opcode_byte: u8, p: u8) -> (Asm8086, Operands) {
+    use Operand::*;
+    match opcode_byte {
+        // immediate to address / register 0o200-0o203 xPm
+        0o200 | 0o202 => (
+            Asm8086::from_arithmetic_p(p),
+            Operands::Two(Eb(Address::ByteRegisterUnread), D(Disp::D8Unread)),
+        ),
+        0o201 => (
+            Asm8086::from_arithmetic_p(p),
+            Operands::Two(Ew(Address::WordRegisterUnread), D(Disp::D16Unread)),
+        ),
+        0o203 => (
+            Asm8086::from_arithmetic_p(p),
+            Operands::Two(Ew(Address::WordRegisterUnread), D(Disp::DcUnread)),
+        ),
+        // shifts and rotations by an immediate count 0o300-0o301 xPm Db, 186 onwards
+        0o300 => (
+            Asm8086::from_shift_p(p),
+            Operands::Two(Eb(Address::ByteRegisterUnread), Du(Disp::D8Unread)),
+        ),
+        0o301 => (
+            Asm8086::from_shift_p(p),
+            Operands::Two(Ew(Address::WordRegisterUnread), Du(Disp::D8Unread)),
+        ),
+        // shifts and rotations by 1 or CL 0o320-0o323 xPm
+        0o320 => (
+            Asm8086::from_shift_p(p),
+            Operands::Two(Eb(Address::ByteRegisterUnread), D(Disp::D8(1))),
+        ),
+        0o321 => (
+            Asm8086::from_shift_p(p),
+            Operands::Two(Ew(Address::WordRegisterUnread), D(Disp::D8(1))),
+        ),
+        0o322 => (
+            Asm8086::from_shift_p(p),
+            Operands::Two(Eb(Address::ByteRegisterUnread), shift_count()),
+        ),
+        0o323 => (
+            Asm8086::from_shift_p(p),
+            Operands::Two(Ew(Address::WordRegisterUnread), shift_count()),
+        ),
+        // test / not / neg / mul / imul / div / idiv 0o366-0o367 xPm
+        0o366 | 0o367 => {
+            let (target, immediate) = match opcode_byte {
+                0o366 => (Eb(Address::ByteRegisterUnread), D(Disp::D8Unread)),
+                _ => (Ew(Address::WordRegisterUnread), D(Disp::D16Unread)),
+            };
+            match p {
+                0 => (Asm8086::Test, Operands::Two(target, immediate)),
+                2 => (Asm8086::Not, Operands::One(target)),
+                3 => (Asm8086::Neg, Operands::One(target)),
+                4 => (Asm8086::Mul, Operands::One(target)),
+                5 => (Asm8086::Imul, Operands::One(target)),
+                6 => (Asm8086::Div, Operands::One(target)),
+                7 => (Asm8086::Idiv, Operands::One(target)),
+                _ => (Asm8086::Unknown, Operands::None),
+            }
+        }
+        // inc / dec byte 0o376 x0m, x1m
+        0o376 => match p {
+            0 => (Asm8086::Inc, Operands::One(Eb(Address::ByteRegisterUnread))),
+            1 => (Asm8086::Dec, Operands::One(Eb(Address::ByteRegisterUnread))),
+            _ => (Asm8086::Unknown, Operands::None),
+        },
+        // inc / dec / call / jmp / push word 0o377 xPm
+        0o377 => match p {
+            0 => (Asm8086::Inc, Operands::One(Ew(Address::WordRegisterUnread))),
+            1 => (Asm8086::Dec, Operands::One(Ew(Address::WordRegisterUnread))),
+            2 => (
+                Asm8086::Call,
+                Operands::One(Ew(Address::WordRegisterUnread)),
+            ),
+            3 => (
+                Asm8086::Call,
+                Operands::One(Ef(Address::WordRegisterUnread)),
+            ),
+            4 => (Asm8086::Jmp, Operands::One(Ew(Address::WordRegisterUnread))),
+            5 => (Asm8086::Jmp, Operands::One(Ef(Address::WordRegisterUnread))),
+            6 => (
+                Asm8086::Push,
+                Operands::One(Ew(Address::WordRegisterUnread)),
+            ),
+            _ => (Asm8086::Unknown, Operands::None),
+        },
+        _ => (Asm8086::Unknown, Operands::None),
+    }
+}
+
+/// 286 protected mode groups 0o017 0o000 and 0o017 0o001, selected by the P digit of xPm.
+fn protected_group_to_instruction(second_byte: u8, p: u8) -> (Asm8086, Operands) {
+    use Operand::*;
+    let word = Ew(Address::WordRegisterUnread);
+    let memory = Em(Address::PointerUnread);
+    match (second_byte, p) {
+        (0o000, 0) => (Asm8086::Sldt, Operands::One(word)),
+        (0o000, 1) => (Asm8086::Str, Operands::One(word)),
+        (0o000, 2) => (Asm8086::Lldt, Operands::One(word)),
+        (0o000, 3) => (Asm8086::Ltr, Operands::One(word)),
+        (0o000, 4) => (Asm8086::Verr, Operands::One(word)),
+        (0o000, 5) => (Asm8086::Verw, Operands::One(word)),
+        (0o001, 0) => (Asm8086::Sgdt, Operands::One(memory)),
+        (0o001, 1) => (Asm8086::Sidt, Operands::One(memory)),
+        (0o001, 2) => (Asm8086::Lgdt, Operands::One(memory)),
+        (0o001, 3) => (Asm8086::Lidt, Operands::One(memory)),
+        (0o001, 4) => (Asm8086::Smsw, Operands::One(word)),
+        (0o001, 6) => (Asm8086::Lmsw, Operands::One(word)),
+        _ => (Asm8086::Unknown, Operands::None),
+    }
+}
+
+/// Instructions behind the 0o017 escape byte other than the two protected mode groups.
+fn extended_to_instruction(second_byte: u8, mode: Mode) -> (Asm8086, Operands) {
+    use Operand::*;
+    let register = Rw(Address::WordRegisterUnread);
+    let word = Ew(Address::WordRegisterUnread);
+    let i386 = mode.cpu >= Cpu::I80386;
+    let long = mode.bits == Bits::SixtyFour;
+    match second_byte {
+        // multi byte nop 0o017 0o037 x0m, as compilers pad 64-bit code with it
+        0o037 if long => (Asm8086::Nop, Operands::One(word)),
+        0o002 => (Asm8086::Lar, Operands::Two(register, word)),
+        0o003 => (Asm8086::Lsl, Operands::Two(register, word)),
+        0o005 => (Asm8086::Loadall, Operands::None),
+        0o006 => (Asm8086::Clts, Operands::None),
+        // near conditional jumps 0o017 200+CC Cw and set byte on condition 220+CC
+        0o200..=0o217 if i386 => (
+            Asm8086::from_cc(second_byte & 0xf),
+            Operands::One(C(Disp::D16Unread)),
+        ),
+        0o220..=0o237 if i386 => (
+            Asm8086::from_set_cc(second_byte & 0xf),
+            Operands::One(Eb(Address::ByteRegisterUnread)),
+        ),
+        // push / pop fs and gs 0o240, 0o241, 0o250, 0o251
+        0o240 | 0o241 | 0o250 | 0o251 if i386 => {
+            let segment = match second_byte {
+                0o240 | 0o241 => SegmentRegister::FS,
+                _ => SegmentRegister::GS,
+            };
+            let op = match second_byte & 1 {
+                0 => Asm8086::Push,
+                _ => Asm8086::Pop,
+            };
+            (op, Operands::One(SR(Address::SegmentRegister(segment))))
+        }
+        0o257 if i386 => (Asm8086::Imul, Operands::Two(register, word)),
+        // zero / sign extend 0o266, 0o267, 0o276, 0o277 xrm
+        0o266 | 0o267 | 0o276 | 0o277 if i386 => {
+            let op = match second_byte {
+                0o266 | 0o267 => Asm8086::Movzx,
+                _ => Asm8086::Movsx,
+            };
+            let source = match second_byte & 1 {
+                0 => Eb(Address::ByteRegisterUnread),
+                _ => word,
+            };
+            (op, Operands::Two(register, source))
+        }
+        _ => (Asm8086::Unknown, Operands::None),
+    }
+}
+
+/// x87 instructions behind the 8086 ESC opcodes 0o330-0o337 xPm.
+/// Memory forms pick the operation and operand size by P, register forms
+/// (x = 3) work on the stack register st(m) or take no operands at all.
+fn esc_to_instruction(opcode_byte: u8, mod_rm: ModRm) -> (Asm8086, Operands) {
+    use Operand::*;
+    let (p, m) = (mod_rm.r_or_s, mod_rm.m);
+    let memory = Address::PointerUnread;
+    if mod_rm.mode != Mod::Register {
+        let (op, operand) = match (opcode_byte, p) {
+            (0o330, _) => (Asm8086::from_fpu_arithmetic_p(p), Ed(memory)),
+            (0o331, 0) => (Asm8086::Fld, Ed(memory)),
+            (0o331, 2) => (Asm8086::Fst, Ed(memory)),
+            (0o331, 3) => (Asm8086::Fstp, Ed(memory)),
+            (0o331, 4) => (Asm8086::Fldenv, Em(memory)),
+            (0o331, 5) => (Asm8086::Fldcw, Ew(memory)),
+            (0o331, 6) => (Asm8086::Fnstenv, Em(memory)),
+            (0o331, 7) => (Asm8086::Fnstcw, Ew(memory)),
+            (0o332, _) => (Asm8086::from_fpu_integer_arithmetic_p(p), Ed(memory)),
+            (0o333, 0) => (Asm8086::Fild, Ed(memory)),
+            (0o333, 2) => (Asm8086::Fist, Ed(memory)),
+            (0o333, 3) => (Asm8086::Fistp, Ed(memory)),
+            (0o333, 5) => (Asm8086::Fld, Et(memory)),
+            (0o333, 7) => (Asm8086::Fstp, Et(memory)),
+            (0o334, _) => (Asm8086::from_fpu_arithmetic_p(p), Eq(memory)),
+            (0o335, 0) => (Asm8086::Fld, Eq(memory)),
+            (0o335, 2) => (Asm8086::Fst, Eq(memory)),
+            (0o335, 3) => (Asm8086::Fstp, Eq(memory)),
+            (0o335, 4) => (Asm8086::Frstor, Em(memory)),
+            (0o335, 6) => (Asm8086::Fnsave, Em(memory)),
+            (0o335, 7) => (Asm8086::Fnstsw, Ew(memory)),
+            (0o336, _) => (Asm8086::from_fpu_integer_arithmetic_p(p), Ew(memory)),
+            (0o337, 0) => (Asm8086::Fild, Ew(memory)),
+            (0o337, 2) => (Asm8086::Fist, Ew(memory)),
+            (0o337, 3) => (Asm8086::Fistp, Ew(memory)),
+            (0o337, 4) => (Asm8086::Fbld, Et(memory)),
+            (0o337, 5) => (Asm8086::Fild, Eq(memory)),
+            (0o337, 6) => (Asm8086::Fbstp, Et(memory)),
+            (0o337, 7) => (Asm8086::Fistp, Eq(memory)),
+            _ => return (Asm8086::Unknown, Operands::None),
+        };
+        return (op, Operands::One(operand));
+    }
+
+    // the reversed subtract and divide swap places when st(i) is the destination
+    let reversed = |p: u8| if p >= 4 { p ^ 1 } else { p };
+    let op = match (opcode_byte, p, m) {
+        (0o330, 2 | 3, _) => return (Asm8086::from_fpu_arithmetic_p(p), Operands::One(St(m))),
+        (0o330, _, _) => {
+            return (
+                Asm8086::from_fpu_arithmetic_p(p),
+                Operands::Two(St(0), St(m)),
+            )
+        }
+        (0o331, 0, _) => return (Asm8086::Fld, Operands::One(St(m))),
+        (0o331, 1, _) => return (Asm8086::Fxch, Operands::One(St(m))),
+        (0o334, 0 | 1 | 4..=7, _) => {
+            return (
+                Asm8086::from_fpu_arithmetic_p(reversed(p)),
+                Operands::Two(St(m), St(0)),
+            )
+        }
+        (0o335, 0, _) => return (Asm8086::Ffree, Operands::One(St(m))),
+        (0o335, 2, _) => return (Asm8086::Fst, Operands::One(St(m))),
+        (0o335, 3, _) => return (Asm8086::Fstp, Operands::One(St(m))),
+        (0o336, 0, _) => Asm8086::Faddp,
+        (0o336, 1, _) => Asm8086::Fmulp,
+        (0o336, 4, _) => Asm8086::Fsubrp,
+        (0o336, 5, _) => Asm8086::Fsubp,
+        (0o336, 6, _) => Asm8086::Fdivrp,
+        (0o336, 7, _) => Asm8086::Fdivp,
+        (0o331, 2, 0) => Asm8086::Fnop,
+        (0o331, 4, 0) => Asm8086::Fchs,
+        (0o331, 4, 1) => Asm8086::Fabs,
+        (0o331, 4, 4) => Asm8086::Ftst,
+        (0o331, 4, 5) => Asm8086::Fxam,
+        (0o331, 5, 0) => Asm8086::Fld1,
+        (0o331, 5, 1) => Asm8086::Fldl2t,
+        (0o331, 5, 2) => Asm8086::Fldl2e,
+        (0o331, 5, 3) => Asm8086::Fldpi,
+        (0o331, 5, 4) => Asm8086::Fldlg2,
+        (0o331, 5, 5) => Asm8086::Fldln2,
+        (0o331, 5, 6) => Asm8086::Fldz,
+        (0o331, 6, 0) => Asm8086::F2xm1,
+        (0o331, 6, 1) => Asm8086::Fyl2x,
+        (0o331, 6, 2) => Asm8086::Fptan,
+        (0o331, 6, 3) => Asm8086::Fpatan,
+        (0o331, 6, 4) => Asm8086::Fxtract,
+        (0o331, 6, 6) => Asm8086::Fdecstp,
+        (0o331, 6, 7) => Asm8086::Fincstp,
+        (0o331, 7, 0) => Asm8086::Fprem,
+        (0o331, 7, 1) => Asm8086::Fyl2xp1,
+        (0o331, 7, 2) => Asm8086::Fsqrt,
+        (0o331, 7, 4) => Asm8086::Frndint,
+        (0o331, 7, 5) => Asm8086::Fscale,
+        // filled in by the 387
+        (0o331, 6, 5) => Asm8086::Fprem1,
+        (0o331, 7, 3) => Asm8086::Fsincos,
+        (0o331, 7, 6) => Asm8086::Fsin,
+        (0o331, 7, 7) => Asm8086::Fcos,
+        (0o333, 4, 0) => Asm8086::Fneni,
+        (0o333, 4, 1) => Asm8086::Fndisi,
+        (0o333, 4, 2) => Asm8086::Fnclex,
+        (0o333, 4, 3) => Asm8086::Fninit,
+        (0o336, 3, 1) => Asm8086::Fcompp,
+        _ => Asm8086::Unknown,
+    };
+    match op {
+        Asm8086::Faddp
+        | Asm8086::Fmulp
+        | Asm8086::Fsubrp
+        | Asm8086::Fsubp
+        | Asm8086::Fdivrp
+        | Asm8086::Fdivp => (op, Operands::Two(St(m), St(0))),
+        _ => (op, Operands::None),
+    }
+}
+
+fn shift_count() -> Operand {
+    Operand::Rb(Address::ByteRegister(ByteRegister::CL))
+}
+
+/// Turns a word sized operand template into the double or quad word form of `size`.
+fn widen_operand(operand: Operand, size: Bits) -> Operand {
+    use Operand::*;
+    match (size, operand) {
+        (Bits::Sixteen, _) => operand,
+        (Bits::ThirtyTwo, Rw(Address::WordRegisterUnread)) => Rd(Address::DwordRegisterUnread),
+        (Bits::ThirtyTwo, Rw(Address::WordRegister(register))) => {
+            Rd(Address::DwordRegister(register.into()))
+        }
+        (Bits::ThirtyTwo, Ew(Address::WordRegisterUnread)) => Ed(Address::DwordRegisterUnread),
+        (Bits::ThirtyTwo, Ew(address @ Address::Pointer(Pointer::Direct(_)))) => Ed(address),
+        (Bits::SixtyFour, Rw(Address::WordRegisterUnread)) => Rq(Address::QwordRegisterUnread),
+        (Bits::SixtyFour, Rw(Address::WordRegister(register))) => {
+            Rq(Address::QwordRegister(register.into()))
+        }
+        (Bits::SixtyFour, Ew(Address::WordRegisterUnread)) => Eq(Address::QwordRegisterUnread),
+        (Bits::SixtyFour, Ew(address @ Address::Pointer(Pointer::Direct(_)))) => Eq(address),
+        // immediates and relative addresses stop at 32 bits, sign extended to 64
+        (_, D(Disp::D16Unread)) => D(Disp::D32Unread),
+        (_, C(Disp::D16Unread)) => C(Disp::D32Unread),
+        (_, Af(Disp::D16Unread, segment)) => Af(Disp::D32Unread, segment),
+        _ => operand,
+    }
+}
+
+fn widen_operands(op: Asm8086, operands: Operands, size: Bits) -> Operands {
+    use Operand::*;
+    match (op, operands) {
+        _ if op.keeps_word_size() => operands,
+        // the port in dx stays a word
+        (Asm8086::In | Asm8086::Out, _) => operands.map(|operand| match operand {
+            Rw(Address::WordRegister(WordRegister::AX)) => widen_operand(operand, size),
+            _ => operand,
+        }),
+        // the extended source keeps its own size
+        (Asm8086::Movzx | Asm8086::Movsx | Asm8086::Movsxd, Operands::Two(target, source)) => {
+            Operands::Two(widen_operand(target, size), source)
+        }
+        _ => operands.map(|operand| widen_operand(operand, size)),
+    }
+}
+
+/// Direct addresses of 0o240-0o243 follow the address size, not the word size.
+fn widen_direct(operand: Operand, size: Bits) -> Operand {
+    use Operand::*;
+    let disp = match size {
+        Bits::Sixteen => return operand,
+        Bits::ThirtyTwo => Disp::D32Unread,
+        Bits::SixtyFour => Disp::D64Unread,
+    };
+    let direct = Address::Pointer(Pointer::direct(disp));
+    match operand {
+        Eb(Address::Pointer(Pointer::Direct(Disp::D16Unread))) => Eb(direct),
+        Ew(Address::Pointer(Pointer::Direct(Disp::D16Unread))) => Ew(direct),
+        Ed(Address::Pointer(Pointer::Direct(Disp::D16Unread))) => Ed(direct),
+        Eq(Address::Pointer(Pointer::Direct(Disp::D16Unread))) => Eq(direct),
+        _ => operand,
+    }
+}
+
+/// Registers encoded in the opcode itself take the REX.B bit as their fourth bit `b`.
+fn extend_opcode_register(operand: Operand, b: u8) -> Operand {
+    use Operand::*;
+    match operand {
+        Rb(Address::ByteRegister(register)) => Rb(Address::ByteRegister(ByteRegister::from_rex_r(
+            register as u8 | b,
+        ))),
+        Rw(Address::WordRegister(register)) => Rw(Address::WordRegister(WordRegister::from_r(
+            register as u8 | b,
+        ))),
+        Rd(Address::DwordRegister(register)) => Rd(Address::DwordRegister(DwordRegister::from_r(
+            register as u8 | b,
+        ))),
+        Rq(Address::QwordRegister(register)) => Rq(Address::QwordRegister(QwordRegister::from_r(
+            register as u8 | b,
+        ))),
+        _ => operand,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mod {
+    MemoryNoDisp,
+    Memory8BitDisp,
+    Memory16BitDisp, // a 32-bit displacement under 32-bit addressing
+    Register,
+}
+
+/// The decoded second `xrm` byte of an instruction and the displacement following it.
+/// Under 32 and 64-bit addressing m = 4 is followed by a scaled index `sir` byte.
+/// The REX prefix of 64-bit mode adds a fourth bit to r, m and the `sir` digits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ModRm {
+    mode: Mod,
+    r_or_s: u8,
+    m: u8,
+    sib: Option<u8>,
+    disp: Disp,
+    address_size: Bits,
+    rex: Option<u8>,
+}
+
+impl ModRm {
+    fn rex_bit(&self, bit: u8) -> u8 {
+        match self.rex {
+            Some(rex) if rex & bit != 0 => 0b1000,
+            _ => 0,
+        }
+    }
+}
+
+pub fn byte_octals(byte: u8) -> (u8, u8, u8) {
+    let first = (byte & 0b11000000) >> 6;
+    let second = (byte & 0b00111000) >> 3;
+    let third = byte & 0b00000111;
+    (first, second, third)
+}
+
+const REX_W: u8 = 0b1000;
+const REX_R: u8 = 0b0100;
+const REX_X: u8 = 0b0010;
+const REX_B: u8 = 0b0001;
+
+fn resolve_mod_operands(byte: u8) -> (Mod, u8, u8) {
+    let (x, r_or_s, m) = byte_octals(byte);
+    let mode = match x {
+        0 => Mod::MemoryNoDisp,
+        1 => Mod::Memory8BitDisp,
+        2 => Mod::Memory16BitDisp,
+        _ => Mod::Register,
+    };
+    (mode, r_or_s, m)
+}
+
+/// Memory operand of a non register `xrm`, from TABLE 1 or under 32-bit addressing TABLE 2.
+fn memory_pointer(mod_rm: ModRm) -> Pointer {
+    let ModRm {
+        mode, m, sib, disp, ..
+    } = mod_rm;
+    if mod_rm.address_size == Bits::Sixteen {
+        return match (mode, m) {
+            (Mod::MemoryNoDisp, 6) => Pointer::direct(disp),
+            _ => Pointer::with_disp(m, disp),
+        };
+    }
+    let (base, index, scale) = match sib {
+        // 64-bit mode reads this one relative to the next instruction instead
+        None if mode == Mod::MemoryNoDisp && m == 5 => match mod_rm.address_size {
+            Bits::SixtyFour => return Pointer::Rip(disp),
+            _ => (None, None, 1),
+        },
+        None => (Some(m | mod_rm.rex_bit(REX_B)), None, 1),
+        Some(sib) => {
+            let (s, i, r) = byte_octals(sib);
+            let base = match (mode, r) {
+                (Mod::MemoryNoDisp, 5) => None,
+                _ => Some(r | mod_rm.rex_bit(REX_B)),
+            };
+            // i = 4 is no index at all, unless REX.X turns it into r12
+            let i = i | mod_rm.rex_bit(REX_X);
+            let index = (i != 4).then_some(i);
+            (base, index, 1 << s)
+        }
+    };
+    match (base, index, mod_rm.address_size) {
+        (None, None, _) => Pointer::direct(disp),
+        (_, _, Bits::SixtyFour) => Pointer::Indexed64 {
+            base: base.map(QwordRegister::from_r),
+            index: index.map(QwordRegister::from_r),
+            scale,
+            disp,
+        },
+        _ => Pointer::Indexed {
+            base: base.map(DwordRegister::from_r),
+            index: index.map(DwordRegister::from_r),
+            scale,
+            disp,
+        },
+    }
+}
+
+fn byte_register(r: u8, rex: Option<u8>) -> ByteRegister {
+    match rex {
+        Some(_) => ByteRegister::from_rex_r(r),
+        None => ByteRegister::from_r(r),
+    }
+}
+
+fn resolve_address(operand: Operand, mod_rm: ModRm, cpu: Cpu) -> Address {
+    use Operand::*;
+    let ModRm {
+        mode, r_or_s, rex, ..
+    } = mod_rm;
+    let r = r_or_s | mod_rm.rex_bit(REX_R);
+    let m = mod_rm.m | mod_rm.rex_bit(REX_B);
+    match (operand, mode) {
+        (Rb(_), _) => Address::ByteRegister(byte_register(r, rex)),
+        (Rw(_), _) => Address::WordRegister(WordRegister::from_r(r)),
+        (Rd(_), _) => Address::DwordRegister(DwordRegister::from_r(r)),
+        (Rq(_), _) => Address::QwordRegister(QwordRegister::from_r(r)),
+        (SR(_), _) if cpu >= Cpu::I80386 => {
+            SegmentRegister::from_s_386(r_or_s).map_or(Address::Unread, Address::SegmentRegister)
+        }
+        (SR(_), _) => Address::SegmentRegister(SegmentRegister::from_s(r_or_s)),
+        (
+            Eb(_) | Ew(_) | Ed(_) | Ef(_) | Eq(_) | Et(_) | Em(_),
+            Mod::MemoryNoDisp | Mod::Memory8BitDisp | Mod::Memory16BitDisp,
+        ) => Address::Pointer(memory_pointer(mod_rm)),
+        (Eb(_), Mod::Register) => Address::ByteRegister(byte_register(m, rex)),
+        (Ew(_) | Ef(_), Mod::Register) => Address::WordRegister(WordRegister::from_r(m)),
+        (Ed(_), Mod::Register) => Address::DwordRegister(DwordRegister::from_r(m)),
+        (Eq(_), Mod::Register) => Address::QwordRegister(QwordRegister::from_r(m)),
+        _ => Address::Unread,
+    }
+}
+
+fn needs_mod_rm(operand: &Operand) -> bool {
+    use Operand::*;
+    matches!(
+        operand,
+        Rb(Address::ByteRegisterUnread)
+            | Rw(Address::WordRegisterUnread)
+            | Eb(Address::ByteRegisterUnread)
+            | Ew(Address::WordRegisterUnread)
+            | Ef(Address::WordRegisterUnread)
+            | SR(Address::SegmentRegisterUnread)
+            | Em(Address::PointerUnread)
+            | Ed(Address::DwordRegisterUnread)
+    )
+}
+
+fn to_word(low_byte: u8, high_byte: u8) -> i16 {
+    ((high_byte as i16) << 8) | (low_byte as i16)
+}
+
+fn next_byte_disp(bytes: &[u8], end_ptr: usize) -> Result<Disp, DecodeErrorKind> {
+    let low_byte = *bytes.get(end_ptr).ok_or(DecodeErrorKind::Truncated)?;
+    Ok(Disp::D8(low_byte as i8))
+}
+
+fn next_word_disp(bytes: &[u8], end_ptr: usize) -> Result<Disp, DecodeErrorKind> {
+    let low_byte = *bytes.get(end_ptr).ok_or(DecodeErrorKind::Truncated)?;
+    let high_byte = *bytes.get(end_ptr + 1).ok_or(DecodeErrorKind::Truncated)?;
+    let disp_word = to_word(low_byte, high_byte);
+    Ok(Disp::D16(disp_word))
+}
+
+fn next_qword_disp(bytes: &[u8], end_ptr: usize) -> Result<Disp, DecodeErrorKind> {
+    let qword = bytes
+        .get(end_ptr..end_ptr + 8)
+        .ok_or(DecodeErrorKind::Truncated)?;
+    let mut le_bytes = [0; 8];
+    le_bytes.copy_from_slice(qword);
+    Ok(Disp::D64(i64::from_le_bytes(le_bytes)))
+}
+
+fn next_dword_disp(bytes: &[u8], end_ptr: usize) -> Result<Disp, DecodeErrorKind> {
+    let dword = bytes
+        .get(end_ptr..end_ptr + 4)
+        .ok_or(DecodeErrorKind::Truncated)?;
+    Ok(Disp::D32(i32::from_le_bytes([
+        dword[0], dword[1], dword[2], dword[3],
+    ])))
+}
+
+fn read_disp(bytes: &[u8], end_ptr: &mut usize, disp: Disp) -> Result<Disp, DecodeErrorKind> {
+    match disp {
+        Disp::D8Unread => {
+            let disp = next_byte_disp(bytes, *end_ptr)?;
+            *end_ptr += 1;
+            Ok(disp)
+        }
+        Disp::D16Unread => {
+            let disp = next_word_disp(bytes, *end_ptr)?;
+            *end_ptr += 2;
+            Ok(disp)
+        }
+        Disp::D32Unread => {
+            let disp = next_dword_disp(bytes, *end_ptr)?;
+            *end_ptr += 4;
+            Ok(disp)
+        }
+        Disp::D64Unread => {
+            let disp = next_qword_disp(bytes, *end_ptr)?;
+            *end_ptr += 8;
+            Ok(disp)
+        }
+        // a signed byte sign extended to a word
+        Disp::DcUnread => {
+            let value = *bytes.get(*end_ptr).ok_or(DecodeErrorKind::Truncated)? as i8;
+            *end_ptr += 1;
+            Ok(Disp::D16(value as i16))
+        }
+        _ => Ok(disp),
+    }
+}
+
+fn read_mod_rm(
+    bytes: &[u8],
+    end_ptr: &mut usize,
+    address_size: Bits,
+    rex: Option<u8>,
+) -> Result<ModRm, DecodeErrorKind> {
+    let second_byte = *bytes.get(*end_ptr).ok_or(DecodeErrorKind::Truncated)?;
+    *end_ptr += 1;
+    let (mode, r_or_s, m) = resolve_mod_operands(second_byte);
+    let wide = address_size != Bits::Sixteen;
+    let sib = match (wide, mode, m) {
+        (true, Mod::MemoryNoDisp | Mod::Memory8BitDisp | Mod::Memory16BitDisp, 4) => {
+            let sib = *bytes.get(*end_ptr).ok_or(DecodeErrorKind::Truncated)?;
+            *end_ptr += 1;
+            Some(sib)
+        }
+        _ => None,
+    };
+    let disp = match (wide, mode, m) {
+        (false, Mod::MemoryNoDisp, 6) | (false, Mod::Memory16BitDisp, _) => Disp::D16Unread,
+        (true, Mod::MemoryNoDisp, 5) | (true, Mod::Memory16BitDisp, _) => Disp::D32Unread,
+        (true, Mod::MemoryNoDisp, 4) if sib.is_some_and(|sib| sib & 0o7 == 5) => Disp::D32Unread,
+        (_, Mod::Memory8BitDisp, _) => Disp::D8Unread,
+        _ => Disp::None,
+    };
+    let disp = read_disp(bytes, end_ptr, disp)?;
+    Ok(ModRm {
+        mode,
+        r_or_s,
+        m,
+        sib,
+        disp,
+        address_size,
+        rex,
+    })
+}
+
+fn resolve_operand(
+    operand: Operand,
+    mod_rm: Option<ModRm>,
+    cpu: Cpu,
+    bytes: &[u8],
+    end_ptr: &mut usize,
+) -> Result<Operand, DecodeErrorKind> {
+    use Operand::*;
+    let resolved = match (operand, mod_rm) {
+        (Rb(Address::ByteRegisterUnread), Some(mod_rm))
+        | (Eb(Address::ByteRegisterUnread), Some(mod_rm)) => {
+            let address = resolve_address(operand, mod_rm, cpu);
+            match operand {
+                Rb(_) => Rb(address),
+                _ => Eb(address),
+            }
+        }
+        (Rw(Address::WordRegisterUnread), Some(mod_rm))
+        | (Ew(Address::WordRegisterUnread), Some(mod_rm)) => {
+            let address = resolve_address(operand, mod_rm, cpu);
+            match operand {
+                Rw(_) => Rw(address),
+                _ => Ew(address),
+            }
+        }
+        (Rd(Address::DwordRegisterUnread), Some(mod_rm))
+        | (Ed(Address::DwordRegisterUnread), Some(mod_rm)) => {
+            let address = resolve_address(operand, mod_rm, cpu);
+            match operand {
+                Rd(_) => Rd(address),
+                _ => Ed(address),
+            }
+        }
+        (Rq(Address::QwordRegisterUnread), Some(mod_rm))
+        | (Eq(Address::QwordRegisterUnread), Some(mod_rm)) => {
+            let address = resolve_address(operand, mod_rm, cpu);
+            match operand {
+                Rq(_) => Rq(address),
+                _ => Eq(address),
+            }
+        }
+        (Ef(Address::WordRegisterUnread), Some(mod_rm)) => {
+            Ef(resolve_address(operand, mod_rm, cpu))
+        }
+        (Ew(Address::PointerUnread), Some(mod_rm))
+        | (Ed(Address::PointerUnread), Some(mod_rm))
+        | (Eq(Address::PointerUnread), Some(mod_rm))
+        | (Et(Address::PointerUnread), Some(mod_rm))
+        | (Em(Address::PointerUnread), Some(mod_rm)) => {
+            let address = resolve_address(operand, mod_rm, cpu);
+            match operand {
+                Ew(_) => Ew(address),
+                Ed(_) => Ed(address),
+                Eq(_) => Eq(address),
+                Et(_) => Et(address),
+                _ => Em(address),
+            }
+        }
+        (SR(Address::SegmentRegisterUnread), Some(mod_rm)) => {
+            SR(resolve_address(operand, mod_rm, cpu))
+        }
+        (Eb(Address::Pointer(Pointer::Direct(disp))), _) => Eb(Address::Pointer(Pointer::direct(
+            read_disp(bytes, end_ptr, disp)?,
+        ))),
+        (Ew(Address::Pointer(Pointer::Direct(disp))), _) => Ew(Address::Pointer(Pointer::direct(
+            read_disp(bytes, end_ptr, disp)?,
+        ))),
+        (Ed(Address::Pointer(Pointer::Direct(disp))), _) => Ed(Address::Pointer(Pointer::direct(
+            read_disp(bytes, end_ptr, disp)?,
+        ))),
+        (Eq(Address::Pointer(Pointer::Direct(disp))), _) => Eq(Address::Pointer(Pointer::direct(
+            read_disp(bytes, end_ptr, disp)?,
+        ))),
+        (D(disp), _) => D(read_disp(bytes, end_ptr, disp)?),
+        (Du(disp), _) => Du(read_disp(bytes, end_ptr, disp)?),
+        (C(disp), _) => C(read_disp(bytes, end_ptr, disp)?),
+        (Af(offset, segment), _) => {
+            let offset = read_disp(bytes, end_ptr, offset)?;
+            Af(offset, read_disp(bytes, end_ptr, segment)?)
+        }
+        _ => operand,
+    };
+    Ok(resolved)
+}
+
+/// Memory only operands that the mod rm byte turned into registers, or segment
+/// registers past the ones the cpu has.
+fn is_invalid_mod_rm(op: Asm8086, operands: &Operands) -> bool {
+    let needs_memory = matches!(op, Asm8086::Lea | Asm8086::Bound);
+    operands.iter().any(|operand| match operand {
+        Operand::Ef(address) => !matches!(address, Address::Pointer(_)),
+        _ => operand.address() == Some(Address::Unread),
+    }) || (needs_memory && !operands.iter().any(|operand| operand.is_memory()))
+}
+
+/// An instruction read up to and including its mod rm byte, the immediates and
+/// direct addresses in its operand templates are still unread.
+struct Template {
+    prefixes: Prefixes,
+    op: Asm8086,
+    operands: Operands,
+    mod_rm: Option<ModRm>,
+    cpu: Cpu,
+    end_ptr: usize,
+}
+
+/// Longest instruction the cpu accepts, prefixes included.
+pub const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+fn too_long(bytes: &[u8], start_ptr: usize) -> DecodeError {
+    let end_ptr = start_ptr + MAX_INSTRUCTION_LENGTH;
+    DecodeError::new(DecodeErrorKind::TooLong, bytes, start_ptr, end_ptr)
+}
+
+/// Running out of bytes means everything from `start_ptr` was consumed, unless
+/// those were already more than an instruction can have.
+fn cut_off(bytes: &[u8], start_ptr: usize) -> DecodeError {
+    if bytes.len() - start_ptr >= MAX_INSTRUCTION_LENGTH {
+        too_long(bytes, start_ptr)
+    } else {
+        DecodeError::new(DecodeErrorKind::Truncated, bytes, start_ptr, bytes.len())
+    }
+}
+
+/// Operand and address size of an instruction with `prefixes` in `mode`.
+fn sizes(mode: Mode, prefixes: &Prefixes) -> (Bits, Bits) {
+    let operand_size = match (mode.bits, prefixes.operand_size) {
+        (Bits::SixtyFour, _) if prefixes.rex.is_some_and(|rex| rex & REX_W != 0) => Bits::SixtyFour,
+        (Bits::Sixteen, false) | (Bits::ThirtyTwo | Bits::SixtyFour, true) => Bits::Sixteen,
+        _ => Bits::ThirtyTwo,
+    };
+    let address_size = match (mode.bits, prefixes.address_size) {
+        (Bits::Sixteen, false) | (Bits::ThirtyTwo, true) => Bits::Sixteen,
+        (Bits::SixtyFour, false) => Bits::SixtyFour,
+        _ => Bits::ThirtyTwo,
+    };
+    (operand_size, address_size)
+}
+
+/// Folds the prefix bytes in front of the instruction at `start_ptr` into `Prefixes`
+/// and finds the opcode byte after them, `None` when the bytes end first.
+pub(crate) fn read_prefixes(
+    bytes: &[u8],
+    start_ptr: usize,
+    mode: Mode,
+) -> Option<(Prefixes, usize)> {
+    let i386 = mode.cpu >= Cpu::I80386;
+    let long = mode.bits == Bits::SixtyFour;
+    let mut prefixes = Prefixes::default();
+    for (end_ptr, &byte) in bytes.iter().enumerate().skip(start_ptr) {
+        // REX only counts right in front of the opcode
+        let rex = prefixes.rex.take();
+        match byte {
+            0o360 => prefixes.lock = true,
+            0o362 => prefixes.repeat = Some(Repeat::Repne),
+            0o363 => prefixes.repeat = Some(Repeat::Rep),
+            0o046 | 0o056 | 0o066 | 0o076 => {
+                let (_, s, _) = byte_octals(byte);
+                prefixes.segment = Some(SegmentRegister::from_s(s));
+            }
+            0o100..=0o117 if long => prefixes.rex = Some(byte & 0o17),
+            0o144 if i386 => prefixes.segment = Some(SegmentRegister::FS),
+            0o145 if i386 => prefixes.segment = Some(SegmentRegister::GS),
+            0o146 if i386 => prefixes.operand_size = true,
+            0o147 if i386 => prefixes.address_size = true,
+            _ => {
+                prefixes.rex = rex;
+                return Some((prefixes, end_ptr));
+            }
+        }
+    }
+    None
+}
+
+/// Reads the prefixes, opcode and mod rm byte of the instruction at `start_ptr`
+/// and sizes its operand templates.
+fn decode_template(bytes: &[u8], start_ptr: usize, mode: Mode) -> Result<Template, DecodeError> {
+    let truncated = |_| cut_off(bytes, start_ptr);
+    let cpu = mode.cpu;
+    let long = mode.bits == Bits::SixtyFour;
+    let (mut prefixes, opcode_ptr) = read_prefixes(bytes, start_ptr, mode)
+        .ok_or(DecodeErrorKind::Truncated)
+        .map_err(truncated)?;
+    let first_byte = bytes[opcode_ptr];
+    let mut end_ptr = opcode_ptr + 1;
+    let rex = prefixes.rex;
+    let (operand_size, address_size) = sizes(mode, &prefixes);
+
+    let (op, operands, mod_rm) = if first_byte == 0o017 && cpu >= Cpu::I80286 {
+        let second_byte = *bytes
+            .get(end_ptr)
+            .ok_or(DecodeErrorKind::Truncated)
+            .map_err(truncated)?;
+        end_ptr += 1;
+        if long && second_byte == 0o036 && end_ptr == bytes.len() {
+            // endbr64 or not is only known from the next byte
+            return Err(truncated(DecodeErrorKind::Truncated));
+        }
+        if long && second_byte == 0o036 && bytes.get(end_ptr) == Some(&0o372) {
+            // endbr64 is a hint nop that needs the rep prefix in front
+            end_ptr += 1;
+            match prefixes.repeat.take() {
+                Some(Repeat::Rep) => (Asm8086::Endbr64, Operands::None, None),
+                _ => (Asm8086::Unknown, Operands::None, None),
+            }
+        } else if matches!(second_byte, 0o000 | 0o001) {
+            let mod_rm = read_mod_rm(bytes, &mut end_ptr, address_size, rex).map_err(truncated)?;
+            let (op, operands) = protected_group_to_instruction(second_byte, mod_rm.r_or_s);
+            (op, operands, Some(mod_rm))
+        } else {
+            let (op, operands) = extended_to_instruction(second_byte, mode);
+            let mod_rm = if operands.iter().any(|operand| needs_mod_rm(&operand)) {
+                Some(read_mod_rm(bytes, &mut end_ptr, address_size, rex).map_err(truncated)?)
+            } else {
+                None
+            };
+            (op, operands, mod_rm)
+        }
+    } else if long && is_invalid_in_long_mode(first_byte) {
+        // before the groups, 0o202 is one of them
+        (Asm8086::Unknown, Operands::None, None)
+    } else if is_group_opcode(first_byte, cpu) {
+        let mod_rm = read_mod_rm(bytes, &mut end_ptr, address_size, rex).map_err(truncated)?;
+        let (op, operands) = match first_byte {
+            0o330..=0o337 => esc_to_instruction(first_byte, mod_rm),
+            _ => group_to_instruction(first_byte, mod_rm.r_or_s),
+        };
+        (op, operands, Some(mod_rm))
+    } else {
+        let (op, operands) = opcode_to_instruction(first_byte, mode);
+        let mod_rm = if operands.iter().any(|operand| needs_mod_rm(&operand)) {
+            Some(read_mod_rm(bytes, &mut end_ptr, address_size, rex).map_err(truncated)?)
+        } else {
+            None
+        };
+        let member = OPCODE_TABLE[first_byte as usize].and_then(|template| template.member);
+        match (member, mod_rm) {
+            (Some(member), Some(mod_rm)) if cpu > Cpu::I8086 && mod_rm.r_or_s != member => {
+                (Asm8086::Unknown, Operands::None, Some(mod_rm))
+            }
+            _ => (op, operands, mod_rm),
+        }
+    };
+
+    // the stack, the flags pushes and near indirect branches of 64-bit mode work on quad words
+    let operand_size = match op {
+        Asm8086::Push
+        | Asm8086::Pop
+        | Asm8086::Pushf
+        | Asm8086::Popf
+        | Asm8086::Call
+        | Asm8086::Jmp
+            if long && operand_size == Bits::ThirtyTwo =>
+        {
+            Bits::SixtyFour
+        }
+        _ => operand_size,
+    };
+    let (op, operands) = match operand_size {
+        Bits::Sixteen => (op, operands),
+        Bits::ThirtyTwo => (op.widened(), widen_operands(op, operands, operand_size)),
+        Bits::SixtyFour => (
+            op.widened_to_qword(),
+            widen_operands(op, operands, operand_size),
+        ),
+    };
+    let operands = match (rex, first_byte) {
+        // mov of a quad word register is the one place a full 64-bit immediate is read
+        (Some(rex), 0o270..=0o277) if rex & REX_W != 0 => operands.map(|operand| match operand {
+            Operand::D(Disp::D32Unread) => Operand::D(Disp::D64Unread),
+            _ => operand,
+        }),
+        _ => operands,
+    };
+    let operands = match (rex, operands) {
+        (Some(rex), Operands::Two(accumulator, register))
+            if (0o221..=0o227).contains(&first_byte) =>
+        {
+            let b = (rex & REX_B) << 3;
+            Operands::Two(accumulator, extend_opcode_register(register, b))
+        }
+        (Some(rex), _) if matches!(first_byte, 0o120..=0o137 | 0o260..=0o277) => {
+            let b = (rex & REX_B) << 3;
+            operands.map(|operand| extend_opcode_register(operand, b))
+        }
+        _ => operands,
+    };
+    let (op, operands) = match address_size {
+        Bits::Sixteen => (op, operands),
+        _ if op == Asm8086::Jcxz => {
+            let op = match address_size {
+                Bits::SixtyFour => Asm8086::Jrcxz,
+                _ => Asm8086::Jecxz,
+            };
+            (op, operands)
+        }
+        _ => (
+            op,
+            operands.map(|operand| widen_direct(operand, address_size)),
+        ),
+    };
+    let length = end_ptr - start_ptr + operands.iter().map(unread_length).sum::<usize>();
+    if length > MAX_INSTRUCTION_LENGTH {
+        return Err(too_long(bytes, start_ptr));
+    }
+    Ok(Template {
+        prefixes,
+        op,
+        operands,
+        mod_rm,
+        cpu,
+        end_ptr,
+    })
+}
+
+/// Decodes the single instruction starting at `start_ptr`.
+fn decode_instruction(
+    bytes: &[u8],
+    start_ptr: usize,
+    mode: Mode,
+) -> Result<DecodedInstruction, DecodeError> {
+    let Template {
+        prefixes,
+        op,
+        operands,
+        mod_rm,
+        cpu,
+        mut end_ptr,
+    } = decode_template(bytes, start_ptr, mode)?;
+    let operands = operands
+        .try_map(|operand| resolve_operand(operand, mod_rm, cpu, bytes, &mut end_ptr))
+        .map_err(|_| DecodeError::new(DecodeErrorKind::Truncated, bytes, start_ptr, bytes.len()))?;
+    if is_invalid_mod_rm(op, &operands) {
+        return Err(DecodeError::new(
+            DecodeErrorKind::InvalidModRm,
+            bytes,
+            start_ptr,
+            end_ptr,
+        ));
+    }
+
+    Ok(DecodedInstruction {
+        prefixes,
+        op,
+        operands,
+        offset: start_ptr,
+        length: end_ptr - start_ptr,
+    })
+}
+
+/// Bytes an operand template still has to read after the mod rm byte.
+fn unread_length(operand: Operand) -> usize {
+    use Operand::*;
+    let disp_length = |disp: Disp| match disp {
+        Disp::D8Unread | Disp::DcUnread => 1,
+        Disp::D16Unread => 2,
+        Disp::D32Unread => 4,
+        Disp::D64Unread => 8,
+        _ => 0,
+    };
+    match operand {
+        D(disp) | Du(disp) | C(disp) => disp_length(disp),
+        Af(offset, segment) => disp_length(offset) + disp_length(segment),
+        Eb(Address::Pointer(Pointer::Direct(disp)))
+        | Ew(Address::Pointer(Pointer::Direct(disp)))
+        | Ed(Address::Pointer(Pointer::Direct(disp)))
+        | Eq(Address::Pointer(Pointer::Direct(disp))) => disp_length(disp),
+        _ => 0,
+    }
+}
+
+/// Length of the 8086 instruction at the start of `bytes`, see `instruction_length_for`.
+pub fn instruction_length(bytes: &[u8]) -> Result<usize, DecodeError> {
+    instruction_length_for(bytes, Mode::default())
+}
+
+/// Length of the instruction at the start of `bytes` from its prefixes, what the
+/// opcode tables say about its mod rm byte and immediate data, and the mod rm byte
+/// itself, without building the instruction. Agrees with the lengths of
+/// `decode_for`, so unknown opcodes and invalid mod rm bytes still give a length.
+pub fn instruction_length_for(bytes: &[u8], mode: Mode) -> Result<usize, DecodeError> {
+    let cpu = mode.cpu;
+    let long = mode.bits == Bits::SixtyFour;
+    let (prefixes, opcode_ptr) = read_prefixes(bytes, 0, mode).ok_or_else(|| cut_off(bytes, 0))?;
+    let (operand_size, address_size) = sizes(mode, &prefixes);
+    let word = if operand_size == Bits::Sixteen { 2 } else { 4 };
+    let opcode = bytes[opcode_ptr];
+    let mut end_ptr = opcode_ptr + 1;
+    let r = |at: usize| bytes.get(at).map(|&mod_rm| byte_octals(mod_rm).1);
+    let (has_mod_rm, immediate) = if opcode == 0o017 && cpu >= Cpu::I80286 {
+        let second_byte = *bytes.get(end_ptr).ok_or_else(|| cut_off(bytes, 0))?;
+        end_ptr += 1;
+        match second_byte {
+            0o036 if long && end_ptr == bytes.len() => return Err(cut_off(bytes, 0)),
+            // endbr64
+            0o036 if long && bytes[end_ptr] == 0o372 => {
+                end_ptr += 1;
+                (false, 0)
+            }
+            0o000 | 0o001 => (true, 0),
+            _ => {
+                let (_, operands) = extended_to_instruction(second_byte, mode);
+                let has_mod_rm = operands.iter().any(|operand| needs_mod_rm(&operand));
+                (has_mod_rm, immediate_length(operands, word))
+            }
+        }
+    } else if long && is_invalid_in_long_mode(opcode) {
+        (false, 0)
+    } else if is_group_opcode(opcode, cpu) {
+        let immediate = match (opcode, r(end_ptr)) {
+            (0o330..=0o337, _) | (_, None) => 0,
+            (_, Some(r)) => immediate_length(group_to_instruction(opcode, r).1, word),
+        };
+        (true, immediate)
+    } else if long && opcode == 0o143 {
+        // movsxd
+        (true, 0)
+    } else {
+        match OPCODE_TABLE[opcode as usize] {
+            Some(template) if cpu >= template.cpu => {
+                let undefined = match (template.member, r(end_ptr)) {
+                    (Some(member), Some(r)) => cpu > Cpu::I8086 && r != member,
+                    _ => false,
+                };
+                let rex_w = prefixes.rex.is_some_and(|rex| rex & REX_W != 0);
+                let immediate = match opcode {
+                    _ if undefined => 0,
+                    // mov of a quad word register reads a full 64-bit immediate
+                    0o270..=0o277 if rex_w => 8,
+                    _ => template
+                        .immediate
+                        .iter()
+                        .map(|part| part.length(word, address_size))
+                        .sum(),
+                };
+                (template.mod_rm, immediate)
+            }
+            _ => (false, 0),
+        }
+    };
+    if has_mod_rm {
+        end_ptr += mod_rm_length(&bytes[end_ptr.min(bytes.len())..], address_size)
+            .ok_or_else(|| cut_off(bytes, 0))?;
+    }
+    let length = end_ptr + immediate;
+    if length > MAX_INSTRUCTION_LENGTH {
+        Err(too_long(bytes, 0))
+    } else if length > bytes.len() {
+        Err(cut_off(bytes, 0))
+    } else {
+        Ok(length)
+    }
+}
+
+/// Bytes of the mod rm byte at the start of `bytes`, its SIB byte and displacement.
+fn mod_rm_length(bytes: &[u8], address_size: Bits) -> Option<usize> {
+    let (mode, _, m) = byte_octals(*bytes.first()?);
+    let wide = address_size != Bits::Sixteen;
+    let sib = usize::from(wide && mode != 3 && m == 4);
+    let disp = match (wide, mode, m) {
+        (_, 3, _) => 0,
+        (_, 1, _) => 1,
+        (false, 0, 6) | (false, 2, _) => 2,
+        (true, 0, 5) | (true, 2, _) => 4,
+        (true, 0, 4) if bytes.get(1)? & 0o7 == 5 => 4,
+        _ => 0,
+    };
+    Some(1 + sib + disp)
+}
+
+/// Bytes of immediate data the operand templates of the hand written group and
+/// 0o017 tables read, `word` being 2 or 4 with the operand size.
+fn immediate_length(operands: Operands, word: usize) -> usize {
+    operands
+        .iter()
+        .map(|operand| match operand {
+            Operand::D(disp) | Operand::Du(disp) | Operand::C(disp) => match disp {
+                Disp::D8Unread | Disp::DcUnread => 1,
+                Disp::D16Unread => word,
+                _ => 0,
+            },
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Decodes the single instruction at `offset`, failing with `UnknownOpcode` where
+/// `decode_for` would give an `Asm8086::Unknown` entry.
+pub fn decode_at(
+    bytes: &[u8],
+    offset: usize,
+    mode: Mode,
+) -> Result<DecodedInstruction, DecodeError> {
+    let instruction = decode_instruction(bytes, offset, mode)?;
+    match instruction.op {
+        Asm8086::Unknown => Err(DecodeError::new(
+            DecodeErrorKind::UnknownOpcode,
+            bytes,
+            offset,
+            offset + instruction.length,
+        )),
+        _ => Ok(instruction),
+    }
+}
+
+/// Decodes a whole 8086 instruction stream. Bytes that do not start a known
+/// instruction are reported as one byte long `Asm8086::Unknown` entries.
+pub fn decode(bytes: &[u8]) -> Result<Vec<DecodedInstruction>, DecodeError> {
+    decode_for(bytes, Mode::default())
+}
+
+/// Decodes with the instruction set and default sizes of `mode` instead of the plain 8086.
+pub fn decode_for(bytes: &[u8], mode: Mode) -> Result<Vec<DecodedInstruction>, DecodeError> {
+    Decoder::new(bytes, mode)
+        .map(|decoded| decoded.map(|(_, _, instruction)| instruction))
+        .collect()
+}
+
+/// Decodes lazily over a borrowed buffer, yielding each instruction with its offset
+/// and raw bytes. Stops after the first error, `Unknown` opcodes are no error here
+/// just as for `decode_for`.
+#[derive(Debug, Clone)]
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    mode: Mode,
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8], mode: Mode) -> Self {
+        Decoder {
+            bytes,
+            mode,
+            offset: 0,
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = Result<(usize, &'a [u8], DecodedInstruction), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset == self.bytes.len() {
+            return None;
+        }
+        match decode_instruction(self.bytes, self.offset, self.mode) {
+            Ok(instruction) => {
+                let start = self.offset;
+                self.offset += instruction.length;
+                Some(Ok((start, &self.bytes[start..self.offset], instruction)))
+            }
+            Err(error) => {
+                self.failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for Decoder<'_> {}
+
+/// Run of bytes that `decode_resync` gave up on and wrote out as `db`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndecodableRegion {
+    pub offset: usize,
+    pub length: usize,
+    /// Why the first byte of the region did not decode.
+    pub kind: DecodeErrorKind,
+}
+
+impl Display for UndecodableRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bytes {}..{} are not code: {}",
+            self.offset,
+            self.offset + self.length,
+            self.kind
+        )
+    }
+}
+
+/// Decodes like `decode_for` but never stops: a byte that does not start a valid
+/// instruction becomes a one byte `db` and decoding retries at the next byte.
+/// Neighbouring `db` bytes are summed up into regions.
+pub fn decode_resync(
+    bytes: &[u8],
+    mode: Mode,
+) -> (Vec<DecodedInstruction>, Vec<UndecodableRegion>) {
+    let mut instructions = Vec::new();
+    let mut regions: Vec<UndecodableRegion> = Vec::new();
+    let mut start_ptr = 0;
+    while start_ptr != bytes.len() {
+        let instruction = match decode_at(bytes, start_ptr, mode) {
+            Ok(instruction) => instruction,
+            Err(error) => {
+                match regions.last_mut() {
+                    Some(region) if region.offset + region.length == start_ptr => {
+                        region.length += 1
+                    }
+                    _ => regions.push(UndecodableRegion {
+                        offset: start_ptr,
+                        length: 1,
+                        kind: error.kind,
+                    }),
+                }
+                DecodedInstruction {
+                    prefixes: Prefixes::default(),
+                    op: Asm8086::Db,
+                    operands: Operands::One(Operand::Data(bytes[start_ptr])),
+                    offset: start_ptr,
+                    length: 1,
+                }
+            }
+        };
+        start_ptr += instruction.length;
+        instructions.push(instruction);
+    }
+    (instructions, regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_to_text(bytes: &[u8]) -> Vec<String> {
+        decode(bytes)
+            .unwrap()
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn can_decode_register_to_register_mov() {
+        assert_eq!(decode_to_text(&[0o211, 0o331]), vec!["mov cx, bx"]);
+    }
+
+    #[test]
+    fn can_decode_mov_with_displacement() {
+        assert_eq!(
+            decode_to_text(&[0o212, 0o140, 0o004]),
+            vec!["mov ah, [bx + si + 4]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o213, 0o127, 0o333]),
+            vec!["mov dx, [bx - 37]"]
+        );
+    }
+
+    #[test]
+    fn can_decode_direct_address() {
+        assert_eq!(
+            decode_to_text(&[0o241, 0o373, 0o011]),
+            vec!["mov ax, [2555]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o213, 0o056, 0o005, 0o000]),
+            vec!["mov bp, [5]"]
+        );
+    }
+
+    #[test]
+    fn can_decode_arithmetic_family() {
+        assert_eq!(decode_to_text(&[0o003, 0o030]), vec!["add bx, [bx + si]"]);
+        assert_eq!(decode_to_text(&[0o010, 0o340]), vec!["or al, ah"]);
+        assert_eq!(decode_to_text(&[0o021, 0o330]), vec!["adc ax, bx"]);
+        assert_eq!(
+            decode_to_text(&[0o032, 0o172, 0o004]),
+            vec!["sbb bh, [bp + si + 4]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o043, 0o173, 0o006]),
+            vec!["and di, [bp + di + 6]"]
+        );
+        assert_eq!(decode_to_text(&[0o054, 0o342]), vec!["sub al, -30"]);
+        assert_eq!(decode_to_text(&[0o065, 0o350, 0o003]), vec!["xor ax, 1000"]);
+        assert_eq!(decode_to_text(&[0o071, 0o136, 0o000]), vec!["cmp [bp], bx"]);
+    }
+
+    #[test]
+    fn can_decode_immediate_to_address_group() {
+        assert_eq!(decode_to_text(&[0o203, 0o306, 0o002]), vec!["add si, 2"]);
+        assert_eq!(
+            decode_to_text(&[0o200, 0o007, 0o042]),
+            vec!["add byte [bx], 34"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o203, 0o202, 0o350, 0o003, 0o035]),
+            vec!["add word [bp + si + 1000], 29"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o203, 0o076, 0o342, 0o022, 0o035]),
+            vec!["cmp word [4834], 29"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o201, 0o351, 0o350, 0o003]),
+            vec!["sub cx, 1000"]
+        );
+    }
+
+    #[test]
+    fn sign_extends_signed_byte_immediate() {
+        let instructions = decode(&[0o203, 0o370, 0o375]).unwrap();
+        assert_eq!(
+            instructions[0].operands,
+            Operands::Two(
+                Operand::Ew(Address::WordRegister(WordRegister::AX)),
+                Operand::D(Disp::D16(-3))
+            )
+        );
+        assert_eq!(instructions[0].to_string(), "cmp ax, -3");
+    }
+
+    #[test]
+    fn can_decode_immediate_to_any_register() {
+        assert_eq!(decode_to_text(&[0o260, 0o001]), vec!["mov al, 1"]);
+        assert_eq!(decode_to_text(&[0o267, 0o377]), vec!["mov bh, -1"]);
+        assert_eq!(decode_to_text(&[0o273, 0o002, 0o001]), vec!["mov bx, 258"]);
+        assert_eq!(
+            decode_to_text(&[0o277, 0o000, 0o200]),
+            vec!["mov di, -32768"]
+        );
+    }
+
+    #[test]
+    fn can_decode_immediate_to_memory() {
+        assert_eq!(
+            decode_to_text(&[0o306, 0o003, 0o007]),
+            vec!["mov byte [bp + di], 7"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o307, 0o205, 0o205, 0o003, 0o133, 0o001]),
+            vec!["mov word [di + 901], 347"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o306, 0o106, 0o113, 0o014]),
+            vec!["mov byte [bp + 75], 12"]
+        );
+    }
+
+    #[test]
+    fn can_decode_mov_listings_without_gaps() {
+        for bytes in [
+            &include_bytes!("../listing_0039_more_movs.bin")[..],
+            &include_bytes!("../listing_0040_challenge_movs.bin")[..],
+        ] {
+            let instructions = decode(bytes).unwrap();
+            assert!(instructions
+                .iter()
+                .all(|instruction| instruction.op == Asm8086::Mov));
+        }
+    }
+
+    #[test]
+    fn can_decode_relative_jumps() {
+        assert_eq!(decode_to_text(&[0o165, 0o002]), vec!["jne $+4"]);
+        assert_eq!(decode_to_text(&[0o174, 0o374]), vec!["jl $-2"]);
+        assert_eq!(decode_to_text(&[0o342, 0o376]), vec!["loop $+0"]);
+        assert_eq!(decode_to_text(&[0o343, 0o000]), vec!["jcxz $+2"]);
+        assert_eq!(decode_to_text(&[0o353, 0o177]), vec!["jmp $+129"]);
+    }
+
+    #[test]
+    fn can_decode_calls_and_returns() {
+        assert_eq!(decode_to_text(&[0o350, 0o375, 0o377]), vec!["call $+0"]);
+        assert_eq!(decode_to_text(&[0o351, 0o000, 0o001]), vec!["jmp $+259"]);
+        assert_eq!(
+            decode_to_text(&[0o232, 0o170, 0o126, 0o064, 0o022]),
+            vec!["call 4660:22136"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o352, 0o000, 0o000, 0o377, 0o377]),
+            vec!["jmp 65535:0"]
+        );
+        assert_eq!(decode_to_text(&[0o303]), vec!["ret"]);
+        assert_eq!(decode_to_text(&[0o302, 0o376, 0o377]), vec!["ret 65534"]);
+        assert_eq!(decode_to_text(&[0o313]), vec!["retf"]);
+        assert_eq!(decode_to_text(&[0o312, 0o004, 0o000]), vec!["retf 4"]);
+    }
+
+    #[test]
+    fn can_decode_interrupts() {
+        assert_eq!(decode_to_text(&[0o314]), vec!["int3"]);
+        assert_eq!(decode_to_text(&[0o315, 0o041]), vec!["int 33"]);
+        assert_eq!(decode_to_text(&[0o315, 0o376]), vec!["int 254"]);
+        assert_eq!(decode_to_text(&[0o316, 0o317]), vec!["into", "iret"]);
+    }
+
+    #[test]
+    fn can_decode_string_instructions() {
+        assert_eq!(
+            decode_to_text(&[0o244, 0o245, 0o246, 0o257]),
+            vec!["movsb", "movsw", "cmpsb", "scasw"]
+        );
+    }
+
+    #[test]
+    fn folds_repeat_prefix_into_instruction() {
+        let instructions = decode(&[0o363, 0o245, 0o363, 0o246, 0o362, 0o257, 0o252]).unwrap();
+        let text: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            text,
+            vec!["rep movsw", "repe cmpsb", "repne scasw", "stosb"]
+        );
+        assert_eq!(instructions[0].length, 2);
+        assert_eq!(instructions[0].prefixes.repeat, Some(Repeat::Rep));
+        assert_eq!(instructions[3].prefixes, Prefixes::default());
+    }
+
+    #[test]
+    fn can_decode_segment_register_movs() {
+        assert_eq!(decode_to_text(&[0o216, 0o332]), vec!["mov ds, dx"]);
+        assert_eq!(decode_to_text(&[0o214, 0o300]), vec!["mov ax, es"]);
+        assert_eq!(
+            decode_to_text(&[0o214, 0o136, 0o002]),
+            vec!["mov [bp + 2], ds"]
+        );
+    }
+
+    #[test]
+    fn can_decode_push_pop_segment_register() {
+        assert_eq!(
+            decode_to_text(&[0o006, 0o016, 0o027, 0o037]),
+            vec!["push es", "push cs", "pop ss", "pop ds"]
+        );
+    }
+
+    #[test]
+    fn shows_segment_override_inside_effective_address() {
+        assert_eq!(
+            decode_to_text(&[0o046, 0o213, 0o002]),
+            vec!["mov ax, [es:bp + si]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o056, 0o306, 0o007, 0o001]),
+            vec!["mov byte [cs:bx], 1"]
+        );
+        assert_eq!(decode_to_text(&[0o046, 0o244]), vec!["es movsb"]);
+        let instructions = decode(&[0o066, 0o241, 0o020, 0o000]).unwrap();
+        assert_eq!(instructions[0].prefixes.segment, Some(SegmentRegister::SS));
+        assert_eq!(instructions[0].to_string(), "mov ax, [ss:16]");
+    }
+
+    #[test]
+    fn can_decode_shifts_and_rotations() {
+        assert_eq!(decode_to_text(&[0o321, 0o340]), vec!["shl ax, 1"]);
+        assert_eq!(decode_to_text(&[0o322, 0o313]), vec!["ror bl, cl"]);
+        assert_eq!(decode_to_text(&[0o323, 0o377]), vec!["sar di, cl"]);
+        assert_eq!(decode_to_text(&[0o320, 0o327]), vec!["rcl bh, 1"]);
+        assert_eq!(decode_to_text(&[0o321, 0o332]), vec!["rcr dx, 1"]);
+        assert_eq!(decode_to_text(&[0o320, 0o357]), vec!["shr bh, 1"]);
+        assert_eq!(decode_to_text(&[0o321, 0o302]), vec!["rol dx, 1"]);
+    }
+
+    #[test]
+    fn annotates_size_of_shifted_memory() {
+        assert_eq!(decode_to_text(&[0o320, 0o047]), vec!["shl byte [bx], 1"]);
+        assert_eq!(
+            decode_to_text(&[0o323, 0o156, 0o004]),
+            vec!["shr word [bp + 4], cl"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o322, 0o076, 0o020, 0o000]),
+            vec!["sar byte [16], cl"]
+        );
+    }
+
+    #[test]
+    fn can_decode_group_3() {
+        assert_eq!(
+            decode_to_text(&[0o366, 0o007, 0o001]),
+            vec!["test byte [bx], 1"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o367, 0o300, 0o000, 0o200]),
+            vec!["test ax, -32768"]
+        );
+        assert_eq!(decode_to_text(&[0o366, 0o323]), vec!["not bl"]);
+        assert_eq!(
+            decode_to_text(&[0o367, 0o136, 0o002]),
+            vec!["neg word [bp + 2]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o366, 0o343, 0o367, 0o351, 0o366, 0o360, 0o367, 0o377]),
+            vec!["mul bl", "imul cx", "div al", "idiv di"]
+        );
+    }
+
+    #[test]
+    fn can_decode_group_4_and_5() {
+        assert_eq!(decode_to_text(&[0o376, 0o300]), vec!["inc al"]);
+        assert_eq!(decode_to_text(&[0o376, 0o017]), vec!["dec byte [bx]"]);
+        assert_eq!(
+            decode_to_text(&[0o377, 0o106, 0o004]),
+            vec!["inc word [bp + 4]"]
+        );
+        assert_eq!(decode_to_text(&[0o377, 0o320]), vec!["call ax"]);
+        assert_eq!(decode_to_text(&[0o377, 0o027]), vec!["call word [bx]"]);
+        assert_eq!(
+            decode_to_text(&[0o377, 0o036, 0o000, 0o001]),
+            vec!["call far [256]"]
+        );
+        assert_eq!(decode_to_text(&[0o377, 0o347]), vec!["jmp di"]);
+        assert_eq!(
+            decode_to_text(&[0o377, 0o154, 0o376]),
+            vec!["jmp far [si - 2]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o377, 0o066, 0o020, 0o000]),
+            vec!["push word [16]"]
+        );
+    }
+
+    #[test]
+    fn can_decode_push_pop_register() {
+        assert_eq!(
+            decode_to_text(&[0o125, 0o211, 0o345, 0o135, 0o303]),
+            vec!["push bp", "mov bp, sp", "pop bp", "ret"]
+        );
+        assert_eq!(decode_to_text(&[0o120, 0o137]), vec!["push ax", "pop di"]);
+    }
+
+    #[test]
+    fn can_decode_exchange() {
+        assert_eq!(decode_to_text(&[0o220]), vec!["nop"]);
+        assert_eq!(decode_to_text(&[0o223]), vec!["xchg ax, bx"]);
+        assert_eq!(decode_to_text(&[0o206, 0o037]), vec!["xchg bl, [bx]"]);
+        assert_eq!(
+            decode_to_text(&[0o207, 0o126, 0o002]),
+            vec!["xchg dx, [bp + 2]"]
+        );
+    }
+
+    #[test]
+    fn can_decode_flag_transfer_and_conversions() {
+        assert_eq!(
+            decode_to_text(&[0o234, 0o235, 0o236, 0o237, 0o230, 0o231]),
+            vec!["pushf", "popf", "sahf", "lahf", "cbw", "cwd"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o370, 0o371, 0o365, 0o374, 0o375, 0o372, 0o373]),
+            vec!["clc", "stc", "cmc", "cld", "std", "cli", "sti"]
+        );
+    }
+
+    #[test]
+    fn can_decode_port_io() {
+        assert_eq!(
+            decode_to_text(&[0o344, 0o040, 0o347, 0o377]),
+            vec!["in al, 32", "out 255, ax"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o354, 0o355, 0o356, 0o357]),
+            vec!["in al, dx", "in ax, dx", "out dx, al", "out dx, ax"]
+        );
+    }
+
+    #[test]
+    fn can_decode_address_loads() {
+        assert_eq!(
+            decode_to_text(&[0o215, 0o160, 0o004]),
+            vec!["lea si, [bx + si + 4]"]
+        );
+        assert_eq!(decode_to_text(&[0o305, 0o067]), vec!["lds si, [bx]"]);
+        assert_eq!(
+            decode_to_text(&[0o304, 0o076, 0o000, 0o002]),
+            vec!["les di, [512]"]
+        );
+    }
+
+    #[test]
+    fn can_decode_bcd_and_misc() {
+        assert_eq!(
+            decode_to_text(&[0o047, 0o057, 0o067, 0o077, 0o327]),
+            vec!["daa", "das", "aaa", "aas", "xlat"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o324, 0o012, 0o325, 0o020]),
+            vec!["aam 10", "aad 16"]
+        );
+        assert_eq!(decode_to_text(&[0o364, 0o233]), vec!["hlt", "wait"]);
+        assert_eq!(
+            decode_to_text(&[0o360, 0o377, 0o007]),
+            vec!["lock inc word [bx]"]
+        );
+    }
+
+    #[test]
+    fn no_unknown_on_documented_8086_opcodes() {
+        // undocumented aliases are not part of the base set
+        let undocumented = |opcode: u8| {
+            matches!(
+                opcode,
+                0o140..=0o157 | 0o300 | 0o301 | 0o310 | 0o311 | 0o326 | 0o361
+            )
+        };
+        for opcode in (0..=255u8).filter(|opcode| !undocumented(*opcode)) {
+            let instruction =
+                decode_instruction(&[opcode, 0, 0, 0, 0, 0, 0], 0, Mode::default()).unwrap();
+            assert_ne!(instruction.op, Asm8086::Unknown, "opcode {:#o}", opcode);
+        }
+    }
+
+    #[test]
+    fn can_decode_x87_memory_operands() {
+        assert_eq!(
+            decode_to_text(&[0o331, 0o006, 0o000, 0o002]),
+            vec!["fld dword [512]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o335, 0o136, 0o370, 0o334, 0o017]),
+            vec!["fstp qword [bp - 8]", "fmul qword [bx]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o333, 0o057, 0o337, 0o044, 0o336, 0o004]),
+            vec!["fld tword [bx]", "fbld tword [si]", "fiadd word [si]"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o331, 0o076, 0o000, 0o001, 0o335, 0o044]),
+            vec!["fnstcw word [256]", "frstor [si]"]
+        );
+    }
+
+    #[test]
+    fn can_decode_x87_stack_registers() {
+        assert_eq!(
+            decode_to_text(&[0o330, 0o301, 0o334, 0o351, 0o336, 0o371]),
+            vec!["fadd st0, st1", "fsub st1, st0", "fdivp st1, st0"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o330, 0o322, 0o336, 0o331]),
+            vec!["fcom st2", "fcompp"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o331, 0o311, 0o335, 0o332]),
+            vec!["fxch st1", "fstp st2"]
+        );
+        assert_eq!(
+            decode_to_text(&[0o331, 0o372, 0o331, 0o376, 0o331, 0o353, 0o333, 0o343]),
+            vec!["fsqrt", "fsin", "fldpi", "fninit"]
+        );
+    }
+
+    fn decode_to_text_for(bytes: &[u8], mode: impl Into<Mode>) -> Vec<String> {
+        decode_for(bytes, mode.into())
+            .unwrap()
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn can_decode_186_additions() {
+        let cpu = Cpu::I80186;
+        assert_eq!(
+            decode_to_text_for(&[0o140, 0o141, 0o311], cpu),
+            vec!["pusha", "popa", "leave"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o150, 0o064, 0o022, 0o152, 0o373], cpu),
+            vec!["push 4660", "push -5"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o153, 0o303, 0o012, 0o151, 0o017, 0o000, 0o001], cpu),
+            vec!["imul ax, bx, 10", "imul cx, [bx], 256"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o301, 0o340, 0o004, 0o300, 0o077, 0o002], cpu),
+            vec!["shl ax, 4", "sar byte [bx], 2"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o310, 0o010, 0o000, 0o000, 0o142, 0o007], cpu),
+            vec!["enter 8, 0", "bound ax, [bx]"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o363, 0o154, 0o157], cpu),
+            vec!["rep insb", "outsw"]
+        );
+    }
+
+    #[test]
+    fn can_decode_286_protected_mode() {
+        let cpu = Cpu::I80286;
+        assert_eq!(
+            decode_to_text_for(&[0o017, 0o000, 0o330, 0o017, 0o001, 0o027], cpu),
+            vec!["ltr ax", "lgdt [bx]"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o017, 0o002, 0o301, 0o017, 0o006, 0o143, 0o330], cpu),
+            vec!["lar ax, cx", "clts", "arpl ax, bx"]
+        );
+    }
+
+    #[test]
+    fn later_opcodes_stay_unknown_on_earlier_cpus() {
+        for opcode in [0o140, 0o150, 0o154, 0o300, 0o310] {
+            let instruction = decode_instruction(&[opcode, 0, 0, 0], 0, Mode::default()).unwrap();
+            assert_eq!(instruction.op, Asm8086::Unknown, "opcode {:#o}", opcode);
+        }
+        assert_eq!(decode_to_text(&[0o017, 0o006]), vec!["pop cs", "push es"]);
+        assert_eq!(
+            decode_to_text_for(&[0o017, 0o006], Cpu::I80186),
+            vec!["pop cs", "push es"]
+        );
+        let arpl = decode_instruction(&[0o143, 0o330], 0, Cpu::I80186.into()).unwrap();
+        assert_eq!(arpl.op, Asm8086::Unknown);
+    }
+
+    #[test]
+    fn can_switch_word_and_address_size_with_prefixes() {
+        let cpu = Cpu::I80386;
+        // EXAMPLE 5 of octal_x86.txt in all four size combinations
+        assert_eq!(
+            decode_to_text_for(
+                &[
+                    0o211, 0o135, 0o375, 0o146, 0o211, 0o135, 0o375, 0o147, 0o211, 0o135, 0o375,
+                    0o146, 0o147, 0o211, 0o135, 0o375,
+                ],
+                cpu
+            ),
+            vec![
+                "mov [di - 3], bx",
+                "mov [di - 3], ebx",
+                "mov [ebp - 3], bx",
+                "mov [ebp - 3], ebx",
+            ]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o146, 0o270, 0o170, 0o126, 0o064, 0o022], cpu),
+            vec!["mov eax, 305419896"]
+        );
+    }
+
+    #[test]
+    fn can_decode_32_bit_addressing() {
+        let mode = Mode::new(Cpu::I80386, Bits::ThirtyTwo);
+        // EXAMPLE 6 of octal_x86.txt
+        assert_eq!(
+            decode_to_text_for(&[0o211, 0o134, 0o302, 0o375], mode),
+            vec!["mov [edx + eax*8 - 3], ebx"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o213, 0o004, 0o044, 0o001, 0o104, 0o044, 0o010], mode),
+            vec!["mov eax, [esp]", "add [esp + 8], eax"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o307, 0o005, 0o000, 0o020, 0, 0, 0o001, 0, 0, 0], mode),
+            vec!["mov dword [4096], 1"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o215, 0o014, 0o215, 0o000, 0o001, 0, 0], mode),
+            vec!["lea ecx, [ecx*4 + 256]"]
+        );
+        assert_eq!(
+            decode_to_text_for(
+                &[0o146, 0o213, 0o103, 0o374, 0o241, 0o000, 0o001, 0, 0],
+                mode
+            ),
+            vec!["mov ax, [ebx - 4]", "mov eax, [256]"]
+        );
+    }
+
+    #[test]
+    fn can_decode_386_extensions() {
+        let mode = Mode::new(Cpu::I80386, Bits::ThirtyTwo);
+        assert_eq!(
+            decode_to_text_for(&[0o017, 0o266, 0o300, 0o017, 0o277, 0o003], mode),
+            vec!["movzx eax, al", "movsx eax, word [ebx]"]
+        );
+        assert_eq!(
+            decode_to_text_for(
+                &[0o017, 0o224, 0o301, 0o017, 0o240, 0o144, 0o213, 0o000],
+                mode
+            ),
+            vec!["sete cl", "push fs", "mov eax, [fs:eax]"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o231, 0o245, 0o146, 0o245, 0o363, 0o253], mode),
+            vec!["cdq", "movsd", "movsw", "rep stosd"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0o017, 0o204, 0o000, 0o001, 0o000, 0o000], mode),
+            vec!["je $+262"]
+        );
+    }
+
+    #[test]
+    fn can_decode_x86_64_subset() {
+        let mode = Mode::new(Cpu::I80386, Bits::SixtyFour);
+        assert_eq!(
+            decode_to_text_for(&[0x55, 0x48, 0x89, 0xe5, 0x48, 0x83, 0xec, 0x10], mode),
+            vec!["push rbp", "mov rbp, rsp", "sub rsp, 16"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0x41, 0x54, 0x41, 0x5c, 0x4c, 0x89, 0xc0], mode),
+            vec!["push r12", "pop r12", "mov rax, r8"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0x48, 0x8d, 0x05, 0x10, 0x00, 0x00, 0x00], mode),
+            vec!["lea rax, [rip + 16]"]
+        );
+        assert_eq!(
+            decode_to_text_for(&[0x48, 0xb8, 0, 0, 0, 0, 1, 0, 0, 0, 0xc3], mode),
+            vec!["mov rax, 4294967296", "ret"]
+        );
+    }
+
+    #[test]
+    fn can_push_and_pop_quad_word_flags() {
+        let mode = Mode::new(Cpu::I80386, Bits::SixtyFour);
+        assert_eq!(
+            decode_to_text_for(&[0x9c, 0x9d, 0x66, 0x9c, 0x66, 0x9d], mode),
+            vec!["pushfq", "popfq", "pushf", "popf"]
+        );
+        let mode = Mode::new(Cpu::I80386, Bits::ThirtyTwo);
+        assert_eq!(
+            decode_to_text_for(&[0x9c, 0x9d], mode),
+            vec!["pushfd", "popfd"]
+        );
+    }
+
+    #[test]
+    fn refuses_opcodes_later_cpus_left_undefined() {
+        let long = Mode::new(Cpu::I80386, Bits::SixtyFour);
+        let instruction = decode_instruction(&[0o202, 0o301, 0o001], 0, long).unwrap();
+        assert_eq!((instruction.op, instruction.length), (Asm8086::Unknown, 1));
+        assert_eq!(instruction_length_for(&[0o202, 0o301, 0o001], long), Ok(1));
+
+        // mov eax, 1 and pop with the r digit 1 instead of 0
+        let mov = [0o307, 0o310, 0o001, 0o000, 0o000, 0o000];
+        for mode in [Mode::new(Cpu::I80386, Bits::ThirtyTwo), long] {
+            assert_eq!(decode_for(&mov, mode).unwrap()[0].op, Asm8086::Unknown);
+            let pop = decode_for(&[0o217, 0o310], mode).unwrap();
+            assert_eq!((pop[0].op, pop[0].length), (Asm8086::Unknown, 2));
+        }
+        // the 8086 ignores the digit
+        assert_eq!(decode_to_text(&[0o306, 0o310, 0o001]), vec!["mov al, 1"]);
+        assert_eq!(decode_to_text(&[0o217, 0o310]), vec!["pop ax"]);
+    }
+
+    #[test]
+    fn can_decode_function_from_elf() {
+        let elf = crate::elf::Elf::parse(include_bytes!("../sum.o")).unwrap();
+        let main = elf.function("main").unwrap();
+        let text = decode_to_text_for(main.bytes, Mode::new(Cpu::I80386, Bits::SixtyFour));
+        assert_eq!(text.len(), 23);
+        assert_eq!(text[..3], ["endbr64", "push rbp", "mov rbp, rsp"]);
+        assert_eq!(text[4], "mov dword [rbp - 12], 0");
+        assert_eq!(text[20..], ["mov eax, 0", "leave", "ret"]);
+    }
+
+    #[test]
+    fn computes_jump_target() {
+        let instructions = decode(&[0o211, 0o331, 0o165, 0o374]).unwrap();
+        assert_eq!(instructions[1].jump_target(), Some(0));
+    }
+
+    #[test]
+    fn keeps_offset_and_length() {
+        let instructions = decode(&[0o211, 0o331, 0o271, 0o001, 0o000]).unwrap();
+        assert_eq!(instructions[1].offset, 2);
+        assert_eq!(instructions[1].length, 3);
+        assert_eq!(instructions[1].to_string(), "mov cx, 1");
+    }
+
+    #[test]
+    fn reports_truncated_instruction() {
+        let error = decode(&[0o211, 0o331, 0o213]).unwrap_err();
+        assert_eq!(error.offset, 2);
+        assert_eq!(error.kind, DecodeErrorKind::Truncated);
+        assert_eq!(error.bytes, vec![0o213]);
+
+        let error = decode(&[0o307, 0o006, 0o020, 0o000, 0o001]).unwrap_err();
+        assert_eq!(error.bytes, vec![0o307, 0o006, 0o020, 0o000, 0o001]);
+    }
+
+    #[test]
+    fn reports_unknown_opcode_and_invalid_mod_rm() {
+        let error = decode_at(&[0o211, 0o331, 0o017, 0o377], 2, Cpu::I80286.into()).unwrap_err();
+        assert_eq!(error.kind, DecodeErrorKind::UnknownOpcode);
+        assert_eq!(error.offset, 2);
+
+        // lea and far jumps only take memory
+        for bytes in [[0o215, 0o300], [0o377, 0o350]] {
+            let error = decode(&bytes).unwrap_err();
+            assert_eq!(error.kind, DecodeErrorKind::InvalidModRm);
+            assert_eq!(error.bytes, bytes.to_vec());
+        }
+        let error = decode_for(&[0o216, 0o370], Cpu::I80386.into()).unwrap_err();
+        assert_eq!(error.kind, DecodeErrorKind::InvalidModRm);
+    }
+
+    #[test]
+    fn reports_instructions_longer_than_15_bytes() {
+        // es prefixes on mov word [16], 1: 9 of them still fit, 10 do not
+        let mut bytes = vec![0o046; 10];
+        bytes.extend([0o307, 0o006, 0o020, 0o000, 0o001, 0o000]);
+        let error = decode_for(&bytes, Mode::default()).unwrap_err();
+        assert_eq!(error.kind, DecodeErrorKind::TooLong);
+        assert_eq!(error.bytes, bytes[..MAX_INSTRUCTION_LENGTH].to_vec());
+        assert_eq!(
+            instruction_length(&bytes).unwrap_err().kind,
+            DecodeErrorKind::TooLong
+        );
+        let instructions = decode_for(&bytes[1..], Mode::default()).unwrap();
+        assert_eq!(instructions[0].length, MAX_INSTRUCTION_LENGTH);
+
+        // prefixes alone run out of room before the bytes do
+        let error = decode_for(&[0o046; 20], Mode::default()).unwrap_err();
+        assert_eq!(error.kind, DecodeErrorKind::TooLong);
+        let error = decode_for(&[0o046; 14], Mode::default()).unwrap_err();
+        assert_eq!(error.kind, DecodeErrorKind::Truncated);
+    }
+
+    #[test]
+    fn can_iterate_over_borrowed_bytes() {
+        let bytes = [0o211, 0o331, 0o271, 0o001, 0o000, 0o213];
+        let mut decoder = Decoder::new(&bytes, Mode::default());
+        let (offset, raw, instruction) = decoder.nth(1).unwrap().unwrap();
+        assert_eq!((offset, raw), (2, &bytes[2..5]));
+        assert_eq!(instruction.to_string(), "mov cx, 1");
+        assert_eq!(decoder.next().unwrap().unwrap_err().offset, 5);
+        assert!(decoder.next().is_none());
+    }
+
+    #[test]
+    fn can_resync_after_undecodable_bytes() {
+        // two escapes the 80286 does not know, then a truncated mov at the very end
+        let bytes = [0o211, 0o331, 0o017, 0o017, 0o211, 0o331, 0o307, 0o307];
+        let (instructions, regions) = decode_resync(&bytes, Cpu::I80286.into());
+        let text: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            text,
+            vec![
+                "mov cx, bx",
+                "db 0o17",
+                "db 0o17",
+                "mov cx, bx",
+                "db 0o307",
+                "db 0o307"
+            ]
+        );
+        assert_eq!(
+            regions,
+            vec![
+                UndecodableRegion {
+                    offset: 2,
+                    length: 2,
+                    kind: DecodeErrorKind::UnknownOpcode
+                },
+                UndecodableRegion {
+                    offset: 6,
+                    length: 2,
+                    kind: DecodeErrorKind::Truncated
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn instruction_length_agrees_with_decode() {
+        let elf = crate::elf::Elf::parse(include_bytes!("../sum.o")).unwrap();
+        let listings = [
+            (
+                &include_bytes!("../listing_0040_challenge_movs.bin")[..],
+                Mode::default(),
+            ),
+            (
+                include_bytes!("../listing_0041_add_sub_cmp_jnz.bin"),
+                Mode::default(),
+            ),
+            (
+                elf.text().unwrap().bytes,
+                Mode::new(Cpu::I80386, Bits::SixtyFour),
+            ),
+        ];
+        for (bytes, mode) in listings {
+            for instruction in decode_for(bytes, mode).unwrap() {
+                let length = instruction_length_for(&bytes[instruction.offset..], mode);
+                assert_eq!(length, Ok(instruction.length), "{}", instruction);
+            }
+        }
+        let modes = [
+            Mode::default(),
+            Cpu::I80186.into(),
+            Cpu::I80286.into(),
+            Mode::new(Cpu::I80386, Bits::Sixteen),
+            Mode::new(Cpu::I80386, Bits::ThirtyTwo),
+            Mode::new(Cpu::I80386, Bits::SixtyFour),
+        ];
+        // every opcode behind each kind of prefix, with mod rm bytes that read a SIB
+        // byte, displacements of all sizes and the r digits of the groups
+        for mode in modes {
+            for opcode in 0..=255u8 {
+                for prefix in [0o220, 0o146, 0o147, 0o017, 0o110] {
+                    for mod_rm in [0o004, 0o005, 0o006, 0o104, 0o204, 0o300, 0o364, 0o373] {
+                        let bytes = [prefix, opcode, mod_rm, 0o045, 0o372, 2, 3, 4, 5, 6, 7];
+                        for end in 1..=bytes.len() {
+                            let length = instruction_length_for(&bytes[..end], mode);
+                            match decode_instruction(&bytes[..end], 0, mode) {
+                                Ok(instruction) => assert_eq!(length, Ok(instruction.length)),
+                                Err(error) if error.kind == DecodeErrorKind::InvalidModRm => {
+                                    assert_eq!(length, Ok(error.bytes.len()))
+                                }
+                                Err(error) => assert_eq!(length, Err(error)),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let error = instruction_length(&[0o307, 0o006, 0o020, 0o000, 0o001]).unwrap_err();
+        assert_eq!(error.kind, DecodeErrorKind::Truncated);
+    }
+
+    #[test]
+    fn instruction_length_agrees_with_decode_for_every_opcode() {
+        let cpus = [Cpu::I8086, Cpu::I80186, Cpu::I80286, Cpu::I80386];
+        let bits = [Bits::Sixteen, Bits::ThirtyTwo, Bits::SixtyFour];
+        let tail = [0o045, 0o372, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        for cpu in cpus {
+            for bits in bits {
+                let mode = Mode::new(cpu, bits);
+                for opcode in 0..=255u8 {
+                    for mod_rm in 0..=255u8 {
+                        let one_byte = [&[opcode, mod_rm][..], &tail].concat();
+                        let two_byte = [&[0o017, opcode, mod_rm][..], &tail].concat();
+                        for bytes in [one_byte, two_byte] {
+                            let length = instruction_length_for(&bytes, mode);
+                            match decode_instruction(&bytes, 0, mode) {
+                                Ok(instruction) => {
+                                    assert_eq!(
+                                        length,
+                                        Ok(instruction.length),
+                                        "{:?} in {:?}",
+                                        bytes,
+                                        mode
+                                    )
+                                }
+                                Err(error) if error.kind == DecodeErrorKind::InvalidModRm => {
+                                    assert_eq!(
+                                        length,
+                                        Ok(error.bytes.len()),
+                                        "{:?} in {:?}",
+                                        bytes,
+                                        mode
+                                    )
+                                }
+                                Err(error) => {
+                                    assert_eq!(length, Err(error), "{:?} in {:?}", bytes, mode)
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn never_panics_on_cut_off_input() {
+        let tails: [&[u8]; 3] = [&[0o377; 15], &[0o004; 15], &[0o104, 0o245, 0o377, 0o377]];
+        let modes = [
+            Mode::default(),
+            Cpu::I80286.into(),
+            Mode::new(Cpu::I80386, Bits::ThirtyTwo),
+            Mode::new(Cpu::I80386, Bits::SixtyFour),
+        ];
+        for mode in modes {
+            for prefix in [None, Some(0o017), Some(0o146), Some(0o147), Some(0o110)] {
+                for opcode in 0..=255u8 {
+                    for tail in tails {
+                        let bytes: Vec<u8> = prefix
+                            .into_iter()
+                            .chain([opcode])
+                            .chain(tail.iter().copied())
+                            .collect();
+                        for end in 0..=bytes.len() {
+                            let _ = decode_for(&bytes[..end], mode);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn can_decode_multiple_mov_listing() {
+        let bytes = include_bytes!("../multiple_mov.bin");
+        let text = decode_to_text(bytes);
+        assert_eq!(text.len(), 11);
+        assert_eq!(text[0], "mov cx, bx");
+    }
+}
@@ -0,0 +1,278 @@
+//! Just enough of ELF64 to find the machine code of `.text` or of a single function,
+//! for disassembling the `sum` / `sum.o` builds next to the course notes.
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EM_X86_64: u16 = 0x3e;
+const SHT_SYMTAB: u32 = 2;
+const STT_FUNC: u8 = 2;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ELF_MAGIC)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub name_index: u32,
+    pub kind: u32,
+    pub address: u64,
+    pub offset: usize,
+    pub size: usize,
+    pub link: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub info: u8,
+    pub section: usize,
+    pub value: u64,
+    pub size: u64,
+}
+
+impl Symbol {
+    pub fn is_function(&self) -> bool {
+        self.info & 0xf == STT_FUNC
+    }
+}
+
+/// Code pulled out of an ELF file and the virtual address its first byte is loaded at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Code<'a> {
+    pub address: u64,
+    pub bytes: &'a [u8],
+}
+
+/// A little endian x86-64 ELF file with its section headers read.
+#[derive(Debug)]
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    pub sections: Vec<Section>,
+}
+
+/// `len` bytes at `at`, an error rather than a panic for offsets read from the file.
+fn read_field(bytes: &[u8], at: usize, len: usize) -> Result<&[u8], String> {
+    at.checked_add(len)
+        .and_then(|end| bytes.get(at..end))
+        .ok_or_else(|| "elf file is truncated".to_string())
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Result<u16, String> {
+    let field = read_field(bytes, at, 2)?;
+    Ok(u16::from_le_bytes([field[0], field[1]]))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32, String> {
+    let field = read_field(bytes, at, 4)?;
+    Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> Result<u64, String> {
+    let field = read_field(bytes, at, 8)?;
+    let mut le_bytes = [0; 8];
+    le_bytes.copy_from_slice(field);
+    Ok(u64::from_le_bytes(le_bytes))
+}
+
+fn read_usize(bytes: &[u8], at: usize) -> Result<usize, String> {
+    usize::try_from(read_u64(bytes, at)?).map_err(|_| "elf offset does not fit".to_string())
+}
+
+/// Zero terminated string at `at` of a string table.
+fn read_str(table: &[u8], at: usize) -> Result<String, String> {
+    let tail = table.get(at..).ok_or("elf string is out of bounds")?;
+    let end = tail
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(tail.len());
+    Ok(String::from_utf8_lossy(&tail[..end]).into_owned())
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        if !is_elf(bytes) {
+            return Err("not an elf file".into());
+        }
+        if bytes.get(4) != Some(&ELFCLASS64) || bytes.get(5) != Some(&ELFDATA2LSB) {
+            return Err("only little endian ELF64 is supported".into());
+        }
+        if read_u16(bytes, 0x12)? != EM_X86_64 {
+            return Err("only x86-64 elf files are supported".into());
+        }
+        let header_offset = read_usize(bytes, 0x28)?;
+        let header_count = read_u16(bytes, 0x3c)? as usize;
+        let names_index = read_u16(bytes, 0x3e)? as usize;
+
+        let mut sections = Vec::with_capacity(header_count);
+        for index in 0..header_count {
+            let at = index
+                .checked_mul(SECTION_HEADER_SIZE)
+                .and_then(|at| at.checked_add(header_offset))
+                .ok_or("elf section header is out of bounds")?;
+            sections.push(Section {
+                // names are filled in once the section name table is known
+                name: String::new(),
+                name_index: read_u32(bytes, at)?,
+                kind: read_u32(bytes, at + 4)?,
+                address: read_u64(bytes, at + 16)?,
+                offset: read_usize(bytes, at + 24)?,
+                size: read_usize(bytes, at + 32)?,
+                link: read_u32(bytes, at + 40)? as usize,
+            });
+        }
+        let mut elf = Elf { bytes, sections };
+        let names = match elf.sections.get(names_index) {
+            Some(section) => elf.section_bytes(section)?,
+            None => return Err("elf section name table is missing".into()),
+        };
+        for section in elf.sections.iter_mut() {
+            section.name = read_str(names, section.name_index as usize)?;
+        }
+        Ok(elf)
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    pub fn section_bytes(&self, section: &Section) -> Result<&'a [u8], String> {
+        section
+            .offset
+            .checked_add(section.size)
+            .and_then(|end| self.bytes.get(section.offset..end))
+            .ok_or_else(|| format!("section '{}' is out of bounds", section.name))
+    }
+
+    pub fn symbols(&self) -> Result<Vec<Symbol>, String> {
+        let mut symbols = Vec::new();
+        for table in self.sections.iter().filter(|s| s.kind == SHT_SYMTAB) {
+            let entries = self.section_bytes(table)?;
+            let names = match self.sections.get(table.link) {
+                Some(section) => self.section_bytes(section)?,
+                None => return Err("elf symbol name table is missing".into()),
+            };
+            for entry in entries.chunks_exact(SYMBOL_SIZE) {
+                symbols.push(Symbol {
+                    name: read_str(names, read_u32(entry, 0)? as usize)?,
+                    info: entry[4],
+                    section: read_u16(entry, 6)? as usize,
+                    value: read_u64(entry, 8)?,
+                    size: read_u64(entry, 16)?,
+                });
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// The whole `.text` section.
+    pub fn text(&self) -> Result<Code<'a>, String> {
+        let text = self.section(".text").ok_or("elf file has no .text")?;
+        Ok(Code {
+            address: text.address,
+            bytes: self.section_bytes(text)?,
+        })
+    }
+
+    /// The bytes of the function symbol `name`. Executables give symbols a virtual
+    /// address, relocatable objects an offset into their section, both work the same.
+    pub fn function(&self, name: &str) -> Result<Code<'a>, String> {
+        let symbol = self
+            .symbols()?
+            .into_iter()
+            .find(|symbol| symbol.name == name && symbol.is_function())
+            .ok_or_else(|| format!("function '{}' not found", name))?;
+        let section = self
+            .sections
+            .get(symbol.section)
+            .ok_or_else(|| format!("function '{}' has no section", name))?;
+        let bytes = self.section_bytes(section)?;
+        let outside = || format!("function '{}' lies outside its section", name);
+        let start = symbol
+            .value
+            .checked_sub(section.address)
+            .and_then(|start| usize::try_from(start).ok())
+            .ok_or_else(outside)?;
+        let end = usize::try_from(symbol.size)
+            .ok()
+            .and_then(|size| start.checked_add(size))
+            .ok_or_else(outside)?;
+        Ok(Code {
+            address: symbol.value,
+            bytes: bytes.get(start..end).ok_or_else(outside)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_find_text_of_object_file() {
+        let elf = Elf::parse(include_bytes!("../sum.o")).unwrap();
+        let text = elf.text().unwrap();
+        assert_eq!(text.address, 0);
+        assert_eq!(text.bytes.len(), 0x55);
+        assert_eq!(&text.bytes[..4], &[0xf3, 0x0f, 0x1e, 0xfa]);
+    }
+
+    #[test]
+    fn can_find_function_of_executable() {
+        let elf = Elf::parse(include_bytes!("../sum")).unwrap();
+        let main = elf.function("main").unwrap();
+        assert_eq!(main.address, 0x1129);
+        assert_eq!(main.bytes.len(), 0x38);
+        assert_eq!(main.bytes.last(), Some(&0xc3));
+        assert!(elf.function("missing").is_err());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(Elf::parse(include_bytes!("../listing_0039_more_movs.bin")).is_err());
+        assert!(Elf::parse(&ELF_MAGIC).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let object = include_bytes!("../sum.o");
+        let elf = Elf::parse(object).unwrap();
+        let text_index = elf.sections.iter().position(|s| s.name == ".text").unwrap();
+        let header_offset = read_usize(object, 0x28).unwrap();
+
+        // a .text size that overflows offset + size
+        let mut bytes = object.to_vec();
+        let size_at = header_offset + text_index * SECTION_HEADER_SIZE + 32;
+        bytes[size_at..size_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let elf = Elf::parse(&bytes).unwrap();
+        assert!(elf.text().is_err());
+
+        // a section header table placed at the very end of the address space
+        let mut bytes = object.to_vec();
+        bytes[0x28..0x30].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Elf::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_function_symbols() {
+        let executable = include_bytes!("../sum");
+        let elf = Elf::parse(executable).unwrap();
+        let table = elf.sections.iter().find(|s| s.kind == SHT_SYMTAB).unwrap();
+        let entries = elf.section_bytes(table).unwrap();
+        let names = elf.section_bytes(&elf.sections[table.link]).unwrap();
+        let main = entries
+            .chunks_exact(SYMBOL_SIZE)
+            .position(|entry| {
+                read_str(names, read_u32(entry, 0).unwrap() as usize).unwrap() == "main"
+            })
+            .unwrap();
+        let size_at = table.offset + main * SYMBOL_SIZE + 16;
+        let mut bytes = executable.to_vec();
+        bytes[size_at..size_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let elf = Elf::parse(&bytes).unwrap();
+        assert!(elf.function("main").is_err());
+    }
+}
@@ -0,0 +1,411 @@
+//! One record per decoded instruction for scripts to read, JSON lines or CSV rows,
+//! so nobody has to pick `mov cx, [1]` apart with regular expressions.
+
+use std::fmt::Write;
+
+use crate::instruction::{Address, DecodedInstruction, Disp, Operand, Pointer};
+
+/// How the command line tool writes the decoded instructions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    /// Address, byte dump and text columns, see `listing`.
+    Listing,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "listing" => Ok(Format::Listing),
+            _ => Err(format!("Unknown format '{}'", text)),
+        }
+    }
+}
+
+/// Typed view of one operand, the fields that do not apply to its kind are `None`.
+///
+/// `kind` is one of `register`, `memory`, `immediate`, `relative`, `far` or `data`.
+/// `value` is the immediate, the absolute jump target, the far offset or the data byte.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OperandRecord {
+    pub kind: &'static str,
+    pub size: Option<u8>, // in bits
+    pub register: Option<String>,
+    pub segment: Option<String>, // segment override of a memory operand
+    pub base: Option<String>,
+    pub index: Option<String>,
+    pub scale: Option<u8>,
+    pub disp: Option<i64>,
+    pub value: Option<i64>,
+    pub selector: Option<u16>, // segment of a far address
+}
+
+const OPERAND_FIELDS: [&str; 10] = [
+    "kind", "size", "register", "segment", "base", "index", "scale", "disp", "value", "selector",
+];
+
+/// Instructions have at most three operands, CSV rows keep columns for all of them.
+const CSV_OPERANDS: usize = 3;
+
+enum Field {
+    Text(String),
+    Number(i64),
+}
+
+fn disp_size(disp: Disp) -> Option<u8> {
+    match disp {
+        Disp::D8(_) => Some(8),
+        Disp::D16(_) => Some(16),
+        Disp::D32(_) => Some(32),
+        Disp::D64(_) => Some(64),
+        _ => None,
+    }
+}
+
+/// Immediates and direct addresses that are read as unsigned numbers.
+fn unsigned(disp: Disp) -> Option<i64> {
+    match disp {
+        Disp::D8(value) => Some(value as u8 as i64),
+        Disp::D16(value) => Some(value as u16 as i64),
+        Disp::D32(value) => Some(value as u32 as i64),
+        Disp::D64(value) => Some(value),
+        _ => None,
+    }
+}
+
+impl OperandRecord {
+    pub fn new(instruction: &DecodedInstruction, operand: Operand) -> Self {
+        let memory_size = match operand {
+            Operand::Eb(_) => Some(8),
+            Operand::Ew(_) => Some(16),
+            Operand::Ed(_) => Some(32),
+            Operand::Eq(_) => Some(64),
+            Operand::Et(_) => Some(80),
+            _ => None,
+        };
+        match (operand, operand.address()) {
+            (_, Some(Address::Pointer(pointer))) => {
+                let (base, index, scale, disp) = pointer.parts();
+                let disp = match pointer {
+                    Pointer::Direct(address) => unsigned(address),
+                    _ => disp.value().map(|disp| disp as i64),
+                };
+                OperandRecord {
+                    kind: "memory",
+                    size: memory_size,
+                    segment: instruction
+                        .prefixes
+                        .segment
+                        .map(|segment| segment.to_string()),
+                    scale: index.as_ref().map(|_| scale),
+                    base,
+                    index,
+                    disp,
+                    ..Default::default()
+                }
+            }
+            (_, Some(address)) => OperandRecord {
+                kind: "register",
+                size: match address {
+                    Address::ByteRegister(_) => Some(8),
+                    Address::WordRegister(_) | Address::SegmentRegister(_) => Some(16),
+                    Address::DwordRegister(_) => Some(32),
+                    Address::QwordRegister(_) => Some(64),
+                    _ => None,
+                },
+                register: Some(address.to_string()),
+                ..Default::default()
+            },
+            (Operand::St(i), None) => OperandRecord {
+                kind: "register",
+                size: Some(80),
+                register: Some(format!("st{}", i)),
+                ..Default::default()
+            },
+            (Operand::D(disp), None) => OperandRecord {
+                kind: "immediate",
+                size: disp_size(disp),
+                value: disp.value().map(|value| value as i64),
+                ..Default::default()
+            },
+            (Operand::Du(disp), None) => OperandRecord {
+                kind: "immediate",
+                size: disp_size(disp),
+                value: unsigned(disp),
+                ..Default::default()
+            },
+            (Operand::C(disp), None) => OperandRecord {
+                kind: "relative",
+                size: disp_size(disp),
+                disp: disp.value().map(|disp| disp as i64),
+                value: instruction.jump_target().map(|target| target as i64),
+                ..Default::default()
+            },
+            (Operand::Af(offset, segment), None) => OperandRecord {
+                kind: "far",
+                value: unsigned(offset),
+                selector: unsigned(segment).map(|segment| segment as u16),
+                ..Default::default()
+            },
+            (Operand::Data(byte), None) => OperandRecord {
+                kind: "data",
+                size: Some(8),
+                value: Some(byte as i64),
+                ..Default::default()
+            },
+            _ => OperandRecord {
+                kind: "unknown",
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Values in `OPERAND_FIELDS` order.
+    fn fields(&self) -> [Option<Field>; 10] {
+        let text = |text: &Option<String>| text.clone().map(Field::Text);
+        let number = |number: Option<i64>| number.map(Field::Number);
+        [
+            Some(Field::Text(self.kind.to_string())),
+            number(self.size.map(i64::from)),
+            text(&self.register),
+            text(&self.segment),
+            text(&self.base),
+            text(&self.index),
+            number(self.scale.map(i64::from)),
+            number(self.disp),
+            number(self.value),
+            number(self.selector.map(i64::from)),
+        ]
+    }
+}
+
+/// `lock`, the repeat prefix, the segment override, `operand_size`, `address_size`
+/// and `rex.wrxb` with the letters of the bits that are set.
+pub fn prefix_names(instruction: &DecodedInstruction) -> Vec<String> {
+    let prefixes = instruction.prefixes;
+    let mut names = Vec::new();
+    if prefixes.lock {
+        names.push("lock".to_string());
+    }
+    if let Some(repeat) = instruction.repeat_text() {
+        names.push(repeat.to_string());
+    }
+    if let Some(segment) = prefixes.segment {
+        names.push(segment.to_string());
+    }
+    if prefixes.operand_size {
+        names.push("operand_size".to_string());
+    }
+    if prefixes.address_size {
+        names.push("address_size".to_string());
+    }
+    if let Some(rex) = prefixes.rex {
+        let bits: String = ['w', 'r', 'x', 'b']
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| rex & (0b1000 >> n) != 0)
+            .map(|(_, letter)| letter)
+            .collect();
+        names.push(format!("rex.{}", bits).trim_end_matches('.').to_string());
+    }
+    names
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).expect("unable to quote"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// One JSON object on a single line. `bytes` are the bytes of this instruction
+/// and `text` how it reads in the chosen syntax.
+pub fn json(instruction: &DecodedInstruction, bytes: &[u8], text: &str) -> String {
+    let mut line = String::new();
+    let bytes: Vec<String> = bytes.iter().map(|byte| byte.to_string()).collect();
+    let prefixes: Vec<String> = prefix_names(instruction)
+        .iter()
+        .map(|name| json_string(name))
+        .collect();
+    let operands: Vec<String> = instruction
+        .operands
+        .iter()
+        .map(|operand| {
+            let fields: Vec<String> = OPERAND_FIELDS
+                .iter()
+                .zip(OperandRecord::new(instruction, operand).fields())
+                .filter_map(|(name, field)| {
+                    let value = match field? {
+                        Field::Text(text) => json_string(&text),
+                        Field::Number(number) => number.to_string(),
+                    };
+                    Some(format!("\"{}\":{}", name, value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        })
+        .collect();
+    write!(
+        line,
+        "{{\"offset\":{},\"length\":{},\"bytes\":[{}],\"prefixes\":[{}],\"mnemonic\":{},\"operands\":[{}],\"text\":{}}}",
+        instruction.offset,
+        instruction.length,
+        bytes.join(","),
+        prefixes.join(","),
+        json_string(&instruction.op.to_string()),
+        operands.join(","),
+        json_string(text)
+    )
+    .expect("unable to write json");
+    line
+}
+
+/// Column names of `csv`, the operand columns are numbered `op1_kind` and so on.
+pub fn csv_header() -> String {
+    let mut columns: Vec<String> = ["offset", "length", "bytes", "prefixes", "mnemonic", "text"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+    for n in 1..=CSV_OPERANDS {
+        columns.extend(
+            OPERAND_FIELDS
+                .iter()
+                .map(|field| format!("op{}_{}", n, field)),
+        );
+    }
+    columns.join(",")
+}
+
+/// One CSV row, bytes as space separated hex pairs and the prefixes space separated.
+pub fn csv(instruction: &DecodedInstruction, bytes: &[u8], text: &str) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let mut columns = vec![
+        instruction.offset.to_string(),
+        instruction.length.to_string(),
+        bytes.join(" "),
+        prefix_names(instruction).join(" "),
+        instruction.op.to_string(),
+        csv_field(text),
+    ];
+    let mut operands = instruction.operands.iter();
+    for _ in 0..CSV_OPERANDS {
+        let fields = match operands.next() {
+            Some(operand) => OperandRecord::new(instruction, operand).fields(),
+            None => Default::default(),
+        };
+        columns.extend(fields.into_iter().map(|field| match field {
+            Some(Field::Text(text)) => csv_field(&text),
+            Some(Field::Number(number)) => number.to_string(),
+            None => String::new(),
+        }));
+    }
+    columns.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{decode, decode_for, Bits, Cpu, Mode};
+
+    #[test]
+    fn can_type_operands() {
+        // mov [bp + di - 37], cl / add word [es:bx], 5 / jne -4
+        let bytes = [
+            0o210, 0o113, 0o333, 0o046, 0o203, 0o007, 0o005, 0o165, 0o374,
+        ];
+        let instructions = decode(&bytes).unwrap();
+        let memory = OperandRecord::new(
+            &instructions[0],
+            instructions[0].operands.iter().next().unwrap(),
+        );
+        assert_eq!(
+            memory,
+            OperandRecord {
+                kind: "memory",
+                size: Some(8),
+                base: Some("bp".into()),
+                index: Some("di".into()),
+                scale: Some(1),
+                disp: Some(-37),
+                ..Default::default()
+            }
+        );
+        let immediate = instructions[1].operands.iter().nth(1).unwrap();
+        assert_eq!(
+            OperandRecord::new(&instructions[1], immediate).value,
+            Some(5)
+        );
+        let jump = OperandRecord::new(
+            &instructions[2],
+            instructions[2].operands.iter().next().unwrap(),
+        );
+        assert_eq!(
+            (jump.kind, jump.disp, jump.value),
+            ("relative", Some(-4), Some(5))
+        );
+    }
+
+    #[test]
+    fn can_write_json() {
+        // es: lock inc word [bx + si + 4]
+        let bytes = [0o046, 0o360, 0o377, 0o100, 0o004];
+        let instruction = decode(&bytes).unwrap()[0];
+        let line = json(&instruction, &bytes, &instruction.to_string());
+        assert_eq!(
+            line,
+            "{\"offset\":0,\"length\":5,\"bytes\":[38,240,255,64,4],\"prefixes\":[\"lock\",\"es\"],\
+             \"mnemonic\":\"inc\",\"operands\":[{\"kind\":\"memory\",\"size\":16,\"segment\":\"es\",\
+             \"base\":\"bx\",\"index\":\"si\",\"scale\":1,\"disp\":4}],\
+             \"text\":\"lock inc word [es:bx + si + 4]\"}"
+        );
+    }
+
+    #[test]
+    fn can_write_csv() {
+        // mov cx, bx / rep movsb
+        let bytes = [0o211, 0o331, 0o363, 0o244];
+        let instructions = decode(&bytes).unwrap();
+        let columns = csv_header().split(',').count();
+        assert_eq!(columns, 6 + CSV_OPERANDS * OPERAND_FIELDS.len());
+        let row = csv(&instructions[0], &bytes[..2], &instructions[0].to_string());
+        assert!(row.starts_with("0,2,89 d9,,mov,\"mov cx, bx\",register,16,cx,"));
+        assert_eq!(row.split(',').count(), columns + 1);
+        let row = csv(&instructions[1], &bytes[2..], &instructions[1].to_string());
+        assert!(row.starts_with("2,2,f3 a4,rep,movsb,rep movsb,,"));
+    }
+
+    #[test]
+    fn can_name_rex_prefixes() {
+        // mov rax, [rbx + rcx*8 - 8]
+        let bytes = [0x48, 0x8b, 0x44, 0xcb, 0xf8];
+        let mode = Mode::new(Cpu::I80386, Bits::SixtyFour);
+        let instruction = decode_for(&bytes, mode).unwrap()[0];
+        assert_eq!(prefix_names(&instruction), vec!["rex.w"]);
+        let memory = OperandRecord::new(&instruction, instruction.operands.iter().nth(1).unwrap());
+        assert_eq!(memory.scale, Some(8));
+        assert_eq!(memory.size, Some(64));
+    }
+}
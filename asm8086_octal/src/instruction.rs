@@ -0,0 +1,1389 @@
+use std::fmt::Display;
+
+use crate::syntax::Syntax;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRegister {
+    AL,
+    CL,
+    DL,
+    BL,
+    AH,
+    CH,
+    DH,
+    BH,
+    SPL,
+    BPL,
+    SIL,
+    DIL,
+    R8B,
+    R9B,
+    R10B,
+    R11B,
+    R12B,
+    R13B,
+    R14B,
+    R15B,
+}
+
+use ByteRegister::*;
+impl ByteRegister {
+    const VALUES: [Self; 8] = [AL, CL, DL, BL, AH, CH, DH, BH];
+    /// With any REX prefix r = 4-7 name the low bytes of SP, BP, SI, DI instead of AH-BH.
+    const REX_VALUES: [Self; 16] = [
+        AL, CL, DL, BL, SPL, BPL, SIL, DIL, R8B, R9B, R10B, R11B, R12B, R13B, R14B, R15B,
+    ];
+    pub const fn from_r(r: u8) -> Self {
+        ByteRegister::VALUES[r as usize]
+    }
+
+    pub fn from_rex_r(r: u8) -> Self {
+        ByteRegister::REX_VALUES[r as usize]
+    }
+}
+
+impl Display for ByteRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match *self {
+            AL => "al",
+            CL => "cl",
+            DL => "dl",
+            BL => "bl",
+            AH => "ah",
+            CH => "ch",
+            DH => "dh",
+            BH => "bh",
+            SPL => "spl",
+            BPL => "bpl",
+            SIL => "sil",
+            DIL => "dil",
+            R8B => "r8b",
+            R9B => "r9b",
+            R10B => "r10b",
+            R11B => "r11b",
+            R12B => "r12b",
+            R13B => "r13b",
+            R14B => "r14b",
+            R15B => "r15b",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WordRegister {
+    AX,
+    CX,
+    DX,
+    BX,
+    SP,
+    BP,
+    SI,
+    DI,
+    R8W,
+    R9W,
+    R10W,
+    R11W,
+    R12W,
+    R13W,
+    R14W,
+    R15W,
+}
+
+use WordRegister::*;
+impl WordRegister {
+    const VALUES: [Self; 16] = [
+        AX, CX, DX, BX, SP, BP, SI, DI, R8W, R9W, R10W, R11W, R12W, R13W, R14W, R15W,
+    ];
+    pub const fn from_r(r: u8) -> Self {
+        WordRegister::VALUES[r as usize]
+    }
+}
+
+impl Display for WordRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match *self {
+            AX => "ax",
+            CX => "cx",
+            DX => "dx",
+            BX => "bx",
+            SP => "sp",
+            BP => "bp",
+            SI => "si",
+            DI => "di",
+            R8W => "r8w",
+            R9W => "r9w",
+            R10W => "r10w",
+            R11W => "r11w",
+            R12W => "r12w",
+            R13W => "r13w",
+            R14W => "r14w",
+            R15W => "r15w",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DwordRegister {
+    EAX,
+    ECX,
+    EDX,
+    EBX,
+    ESP,
+    EBP,
+    ESI,
+    EDI,
+    R8D,
+    R9D,
+    R10D,
+    R11D,
+    R12D,
+    R13D,
+    R14D,
+    R15D,
+}
+
+use DwordRegister::*;
+impl DwordRegister {
+    /// Registers 8-15 are only reachable through the REX prefix of 64-bit mode.
+    const VALUES: [Self; 16] = [
+        EAX, ECX, EDX, EBX, ESP, EBP, ESI, EDI, R8D, R9D, R10D, R11D, R12D, R13D, R14D, R15D,
+    ];
+    pub fn from_r(r: u8) -> Self {
+        DwordRegister::VALUES[r as usize]
+    }
+}
+
+impl From<WordRegister> for DwordRegister {
+    fn from(register: WordRegister) -> Self {
+        DwordRegister::from_r(register as u8)
+    }
+}
+
+impl Display for DwordRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match *self {
+            EAX => "eax",
+            ECX => "ecx",
+            EDX => "edx",
+            EBX => "ebx",
+            ESP => "esp",
+            EBP => "ebp",
+            ESI => "esi",
+            EDI => "edi",
+            R8D => "r8d",
+            R9D => "r9d",
+            R10D => "r10d",
+            R11D => "r11d",
+            R12D => "r12d",
+            R13D => "r13d",
+            R14D => "r14d",
+            R15D => "r15d",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QwordRegister {
+    RAX,
+    RCX,
+    RDX,
+    RBX,
+    RSP,
+    RBP,
+    RSI,
+    RDI,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+use QwordRegister::*;
+impl QwordRegister {
+    const VALUES: [Self; 16] = [
+        RAX, RCX, RDX, RBX, RSP, RBP, RSI, RDI, R8, R9, R10, R11, R12, R13, R14, R15,
+    ];
+    pub fn from_r(r: u8) -> Self {
+        QwordRegister::VALUES[r as usize]
+    }
+}
+
+impl From<WordRegister> for QwordRegister {
+    fn from(register: WordRegister) -> Self {
+        QwordRegister::from_r(register as u8)
+    }
+}
+
+impl Display for QwordRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match *self {
+            RAX => "rax",
+            RCX => "rcx",
+            RDX => "rdx",
+            RBX => "rbx",
+            RSP => "rsp",
+            RBP => "rbp",
+            RSI => "rsi",
+            RDI => "rdi",
+            R8 => "r8",
+            R9 => "r9",
+            R10 => "r10",
+            R11 => "r11",
+            R12 => "r12",
+            R13 => "r13",
+            R14 => "r14",
+            R15 => "r15",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentRegister {
+    ES,
+    CS,
+    SS,
+    DS,
+    FS,
+    GS,
+}
+
+use SegmentRegister::*;
+impl SegmentRegister {
+    const VALUES: [Self; 6] = [ES, CS, SS, DS, FS, GS];
+    /// The 8086 only looks at the low two bits of s, so 4-7 alias 0-3.
+    pub const fn from_s(s: u8) -> Self {
+        SegmentRegister::VALUES[(s & 0b11) as usize]
+    }
+
+    /// The 386 adds FS and GS as s = 4, 5, while 6 and 7 are reserved.
+    pub fn from_s_386(s: u8) -> Option<Self> {
+        SegmentRegister::VALUES.get(s as usize).copied()
+    }
+}
+
+impl Display for SegmentRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match *self {
+            ES => "es",
+            CS => "cs",
+            SS => "ss",
+            DS => "ds",
+            FS => "fs",
+            GS => "gs",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pointer {
+    BxSi(Disp),
+    BxDi(Disp),
+    BpSi(Disp),
+    BpDi(Disp),
+    Si(Disp),
+    Di(Disp),
+    Direct(Disp),
+    Bp(Disp),
+    Bx(Disp),
+    /// 32-bit address `[base + index*scale + disp]`, any part of which may be absent.
+    Indexed {
+        base: Option<DwordRegister>,
+        index: Option<DwordRegister>,
+        scale: u8,
+        disp: Disp,
+    },
+    /// 64-bit address, laid out like `Indexed`.
+    Indexed64 {
+        base: Option<QwordRegister>,
+        index: Option<QwordRegister>,
+        scale: u8,
+        disp: Disp,
+    },
+    /// 64-bit mode address relative to the next instruction.
+    Rip(Disp),
+    Unread,
+}
+
+fn write_indexed(
+    f: &mut std::fmt::Formatter<'_>,
+    base: Option<&dyn Display>,
+    index: Option<&dyn Display>,
+    scale: u8,
+    disp: Disp,
+) -> std::fmt::Result {
+    write!(f, "[")?;
+    if let Some(base) = base {
+        write!(f, "{}", base)?;
+    }
+    if let Some(index) = index {
+        let separator = if base.is_some() { " + " } else { "" };
+        write!(f, "{}{}", separator, index)?;
+        if scale > 1 {
+            write!(f, "*{}", scale)?;
+        }
+    }
+    write!(f, "{}]", disp)
+}
+
+impl Pointer {
+    pub fn with_disp(m: u8, disp: Disp) -> Self {
+        match m {
+            0 => Pointer::BxSi(disp),
+            1 => Pointer::BxDi(disp),
+            2 => Pointer::BpSi(disp),
+            3 => Pointer::BpDi(disp),
+            4 => Pointer::Si(disp),
+            5 => Pointer::Di(disp),
+            6 => Pointer::Bp(disp),
+            7 => Pointer::Bx(disp),
+            _ => Pointer::Unread,
+        }
+    }
+
+    pub const fn direct(disp: Disp) -> Self {
+        Pointer::Direct(disp)
+    }
+
+    /// Base and index register names, scale and displacement, for layouts other
+    /// than `[base + index*scale + disp]`. Direct addresses have neither register.
+    pub fn parts(&self) -> (Option<String>, Option<String>, u8, Disp) {
+        use Pointer::*;
+        let name = |register: &dyn Display| Some(register.to_string());
+        match *self {
+            BxSi(disp) => (name(&"bx"), name(&"si"), 1, disp),
+            BxDi(disp) => (name(&"bx"), name(&"di"), 1, disp),
+            BpSi(disp) => (name(&"bp"), name(&"si"), 1, disp),
+            BpDi(disp) => (name(&"bp"), name(&"di"), 1, disp),
+            Si(disp) => (name(&"si"), None, 1, disp),
+            Di(disp) => (name(&"di"), None, 1, disp),
+            Bp(disp) => (name(&"bp"), None, 1, disp),
+            Bx(disp) => (name(&"bx"), None, 1, disp),
+            Indexed {
+                base,
+                index,
+                scale,
+                disp,
+            } => (
+                base.as_ref().and_then(|base| name(base)),
+                index.as_ref().and_then(|index| name(index)),
+                scale,
+                disp,
+            ),
+            Indexed64 {
+                base,
+                index,
+                scale,
+                disp,
+            } => (
+                base.as_ref().and_then(|base| name(base)),
+                index.as_ref().and_then(|index| name(index)),
+                scale,
+                disp,
+            ),
+            Rip(disp) => (name(&"rip"), None, 1, disp),
+            Direct(disp) => (None, None, 1, disp),
+            Unread => (None, None, 1, Disp::None),
+        }
+    }
+}
+
+impl Display for Pointer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Pointer::*;
+        match *self {
+            BxSi(disp) => write!(f, "[bx + si{}]", disp),
+            BxDi(disp) => write!(f, "[bx + di{}]", disp),
+            BpSi(disp) => write!(f, "[bp + si{}]", disp),
+            BpDi(disp) => write!(f, "[bp + di{}]", disp),
+            Si(disp) => write!(f, "[si{}]", disp),
+            Di(disp) => write!(f, "[di{}]", disp),
+            Direct(Disp::D16(address)) => write!(f, "[{}]", address as u16),
+            Direct(Disp::D8(address)) => write!(f, "[{}]", address as u8),
+            Direct(Disp::D32(address)) => write!(f, "[{}]", address as u32),
+            Direct(Disp::D64(address)) => write!(f, "[{}]", address as u64),
+            Direct(disp) => write!(f, "[{:?}]", disp),
+            Bp(disp) => write!(f, "[bp{}]", disp),
+            Bx(disp) => write!(f, "[bx{}]", disp),
+            Indexed {
+                base,
+                index,
+                scale,
+                disp,
+            } => write_indexed(
+                f,
+                base.as_ref().map(|base| base as &dyn Display),
+                index.as_ref().map(|index| index as &dyn Display),
+                scale,
+                disp,
+            ),
+            Indexed64 {
+                base,
+                index,
+                scale,
+                disp,
+            } => write_indexed(
+                f,
+                base.as_ref().map(|base| base as &dyn Display),
+                index.as_ref().map(|index| index as &dyn Display),
+                scale,
+                disp,
+            ),
+            Rip(disp) => write!(f, "[rip{}]", disp),
+            Unread => write!(f, "Unread"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Address {
+    ByteRegister(ByteRegister),
+    WordRegister(WordRegister),
+    DwordRegister(DwordRegister),
+    QwordRegister(QwordRegister),
+    SegmentRegister(SegmentRegister),
+    Pointer(Pointer),
+    ByteRegisterUnread,
+    WordRegisterUnread,
+    DwordRegisterUnread,
+    QwordRegisterUnread,
+    SegmentRegisterUnread,
+    PointerUnread,
+    Unread,
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Address::*;
+        match *self {
+            ByteRegister(register) => write!(f, "{}", register),
+            WordRegister(register) => write!(f, "{}", register),
+            DwordRegister(register) => write!(f, "{}", register),
+            QwordRegister(register) => write!(f, "{}", register),
+            SegmentRegister(register) => write!(f, "{}", register),
+            Pointer(pointer) => write!(f, "{}", pointer),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Rb(Address),
+    Rw(Address),
+    Rd(Address),    // double word register, a word register under 32-bit operand size
+    Rq(Address),    // quad word register, a word register under 64-bit operand size
+    Eb(Address),    // effective address byte
+    Ew(Address),    // effective word
+    Ef(Address),    // effective address of a far pointer
+    D(Disp),        // immediate data
+    Du(Disp),       // unsigned immediate data: interrupt numbers, ports, counts
+    C(Disp),        // code address relative to the next instruction
+    Af(Disp, Disp), // absolute far code address, offset then segment
+    SR(Address),    // segment register
+    Ed(Address),    // effective double word, x87 single real or long integer
+    Eq(Address),    // effective quad word, x87 double real or quad integer
+    Et(Address),    // effective ten bytes, x87 extended real or packed decimal
+    Em(Address),    // effective memory without a size, x87 environment and state
+    St(u8),         // x87 stack register st(i)
+    Data(u8),       // raw byte of a db directive
+}
+
+impl Operand {
+    pub fn address(&self) -> Option<Address> {
+        use Operand::*;
+        match *self {
+            Rb(address) | Rw(address) | Rd(address) | Rq(address) | Eb(address) | Ew(address)
+            | Ef(address) | SR(address) | Ed(address) | Eq(address) | Et(address) | Em(address) => {
+                Some(address)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_memory(&self) -> bool {
+        matches!(self.address(), Some(Address::Pointer(_)))
+    }
+
+    pub fn is_register(&self) -> bool {
+        matches!(
+            self.address(),
+            Some(
+                Address::ByteRegister(_)
+                    | Address::WordRegister(_)
+                    | Address::DwordRegister(_)
+                    | Address::QwordRegister(_)
+                    | Address::SegmentRegister(_)
+            )
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Disp {
+    None,
+    D8(i8),
+    D16(i16),
+    D32(i32),
+    D64(i64),
+    D8Unread,
+    D16Unread,
+    D32Unread,
+    D64Unread,
+    DcUnread, // signed byte sign-extended to a word
+}
+
+impl Disp {
+    pub fn value(&self) -> Option<isize> {
+        match *self {
+            Disp::D8(x) => Some(x as isize),
+            Disp::D16(x) => Some(x as isize),
+            Disp::D32(x) => Some(x as isize),
+            Disp::D64(x) => Some(x as isize),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Disp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Disp::*;
+        match *self {
+            None | D8(0) | D16(0) | D32(0) | D64(0) => write!(f, ""),
+            D8(x) if x < 0 => write!(f, " - {}", -(x as i16)),
+            D16(x) if x < 0 => write!(f, " - {}", -(x as i32)),
+            D32(x) if x < 0 => write!(f, " - {}", -(x as i64)),
+            D64(x) if x < 0 => write!(f, " - {}", -(x as i128)),
+            D8(x) => write!(f, " + {}", x),
+            D16(x) => write!(f, " + {}", x),
+            D32(x) => write!(f, " + {}", x),
+            D64(x) => write!(f, " + {}", x),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Operand::*;
+        match *self {
+            Rb(inner) => write!(f, "{}", inner),
+            Rw(inner) => write!(f, "{}", inner),
+            Rd(inner) => write!(f, "{}", inner),
+            Rq(inner) => write!(f, "{}", inner),
+            Eb(inner) => write!(f, "{}", inner),
+            Ew(inner) => write!(f, "{}", inner),
+            Ef(inner) => write!(f, "{}", inner),
+            SR(inner) => write!(f, "{}", inner),
+            Ed(inner) | Eq(inner) | Et(inner) | Em(inner) => write!(f, "{}", inner),
+            St(i) => write!(f, "st{}", i),
+            Data(byte) => write!(f, "{:#o}", byte),
+            D(Disp::D8(value)) => write!(f, "{}", value),
+            D(Disp::D16(value)) => write!(f, "{}", value),
+            D(Disp::D32(value)) => write!(f, "{}", value),
+            D(Disp::D64(value)) => write!(f, "{}", value),
+            Du(Disp::D8(value)) => write!(f, "{}", value as u8),
+            Du(Disp::D16(value)) => write!(f, "{}", value as u16),
+            Af(Disp::D16(offset), Disp::D16(segment)) => {
+                write!(f, "{}:{}", segment as u16, offset as u16)
+            }
+            Af(Disp::D32(offset), Disp::D16(segment)) => {
+                write!(f, "{}:{}", segment as u16, offset as u32)
+            }
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// Operands of an instruction, kept inline so decoding does not allocate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operands {
+    None,
+    One(Operand),
+    Two(Operand, Operand),
+    Three(Operand, Operand, Operand),
+}
+
+impl Operands {
+    pub fn iter(&self) -> impl Iterator<Item = Operand> {
+        let operands = match *self {
+            Operands::None => [None, None, None],
+            Operands::One(first) => [Some(first), None, None],
+            Operands::Two(first, second) => [Some(first), Some(second), None],
+            Operands::Three(first, second, third) => [Some(first), Some(second), Some(third)],
+        };
+        operands.into_iter().flatten()
+    }
+
+    pub fn map(self, mut f: impl FnMut(Operand) -> Operand) -> Self {
+        self.try_map(|operand| Ok::<_, std::convert::Infallible>(f(operand)))
+            .unwrap_or_else(|never| match never {})
+    }
+
+    pub fn try_map<E>(self, mut f: impl FnMut(Operand) -> Result<Operand, E>) -> Result<Self, E> {
+        let operands = match self {
+            Operands::None => Operands::None,
+            Operands::One(first) => Operands::One(f(first)?),
+            Operands::Two(first, second) => {
+                let first = f(first)?;
+                Operands::Two(first, f(second)?)
+            }
+            Operands::Three(first, second, third) => {
+                let first = f(first)?;
+                let second = f(second)?;
+                Operands::Three(first, second, f(third)?)
+            }
+        };
+        Ok(operands)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Asm8086 {
+    Mov,
+    Add,
+    Or,
+    Adc,
+    Sbb,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+    Jo,
+    Jno,
+    Jb,
+    Jnb,
+    Je,
+    Jne,
+    Jbe,
+    Ja,
+    Js,
+    Jns,
+    Jp,
+    Jnp,
+    Jl,
+    Jnl,
+    Jle,
+    Jg,
+    Loopnz,
+    Loopz,
+    Loop,
+    Jcxz,
+    Jmp,
+    Call,
+    Ret,
+    Retf,
+    Int,
+    Int3,
+    Into,
+    Iret,
+    Movsb,
+    Movsw,
+    Cmpsb,
+    Cmpsw,
+    Stosb,
+    Stosw,
+    Lodsb,
+    Lodsw,
+    Scasb,
+    Scasw,
+    Push,
+    Pop,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Shl,
+    Shr,
+    Sar,
+    Test,
+    Not,
+    Neg,
+    Mul,
+    Imul,
+    Div,
+    Idiv,
+    Inc,
+    Dec,
+    Xchg,
+    Nop,
+    Pushf,
+    Popf,
+    Sahf,
+    Lahf,
+    Cbw,
+    Cwd,
+    Clc,
+    Stc,
+    Cmc,
+    Cld,
+    Std,
+    Cli,
+    Sti,
+    Daa,
+    Das,
+    Aaa,
+    Aas,
+    Aam,
+    Aad,
+    Lea,
+    Lds,
+    Les,
+    Xlat,
+    In,
+    Out,
+    Hlt,
+    Wait,
+    Fadd,
+    Fmul,
+    Fcom,
+    Fcomp,
+    Fsub,
+    Fsubr,
+    Fdiv,
+    Fdivr,
+    Fiadd,
+    Fimul,
+    Ficom,
+    Ficomp,
+    Fisub,
+    Fisubr,
+    Fidiv,
+    Fidivr,
+    Faddp,
+    Fmulp,
+    Fcompp,
+    Fsubrp,
+    Fsubp,
+    Fdivrp,
+    Fdivp,
+    Fld,
+    Fst,
+    Fstp,
+    Fild,
+    Fist,
+    Fistp,
+    Fbld,
+    Fbstp,
+    Fxch,
+    Ffree,
+    Fldenv,
+    Fldcw,
+    Fnstenv,
+    Fnstcw,
+    Frstor,
+    Fnsave,
+    Fnstsw,
+    Fneni,
+    Fndisi,
+    Fnclex,
+    Fninit,
+    Fnop,
+    Fchs,
+    Fabs,
+    Ftst,
+    Fxam,
+    Fld1,
+    Fldl2t,
+    Fldl2e,
+    Fldpi,
+    Fldlg2,
+    Fldln2,
+    Fldz,
+    F2xm1,
+    Fyl2x,
+    Fptan,
+    Fpatan,
+    Fxtract,
+    Fprem1,
+    Fdecstp,
+    Fincstp,
+    Fprem,
+    Fyl2xp1,
+    Fsqrt,
+    Fsincos,
+    Frndint,
+    Fscale,
+    Fsin,
+    Fcos,
+    Pusha,
+    Popa,
+    Bound,
+    Arpl,
+    Insb,
+    Insw,
+    Outsb,
+    Outsw,
+    Enter,
+    Leave,
+    Sldt,
+    Str,
+    Lldt,
+    Ltr,
+    Verr,
+    Verw,
+    Sgdt,
+    Sidt,
+    Lgdt,
+    Lidt,
+    Smsw,
+    Lmsw,
+    Lar,
+    Lsl,
+    Loadall,
+    Clts,
+    Movsd,
+    Cmpsd,
+    Stosd,
+    Lodsd,
+    Scasd,
+    Insd,
+    Outsd,
+    Cwde,
+    Cdq,
+    Pushad,
+    Popad,
+    Pushfd,
+    Popfd,
+    Iretd,
+    Jecxz,
+    Movzx,
+    Movsx,
+    Seto,
+    Setno,
+    Setb,
+    Setnb,
+    Sete,
+    Setne,
+    Setbe,
+    Seta,
+    Sets,
+    Setns,
+    Setp,
+    Setnp,
+    Setl,
+    Setnl,
+    Setle,
+    Setg,
+    Cdqe,
+    Cqo,
+    Movsq,
+    Cmpsq,
+    Stosq,
+    Lodsq,
+    Scasq,
+    Iretq,
+    Pushfq,
+    Popfq,
+    Jrcxz,
+    Movsxd,
+    Endbr64,
+    Db,
+    Unknown,
+}
+
+impl Asm8086 {
+    const ARITHMETIC: [Self; 8] = [
+        Self::Add,
+        Self::Or,
+        Self::Adc,
+        Self::Sbb,
+        Self::And,
+        Self::Sub,
+        Self::Xor,
+        Self::Cmp,
+    ];
+
+    const CONDITIONAL_JUMPS: [Self; 16] = [
+        Self::Jo,
+        Self::Jno,
+        Self::Jb,
+        Self::Jnb,
+        Self::Je,
+        Self::Jne,
+        Self::Jbe,
+        Self::Ja,
+        Self::Js,
+        Self::Jns,
+        Self::Jp,
+        Self::Jnp,
+        Self::Jl,
+        Self::Jnl,
+        Self::Jle,
+        Self::Jg,
+    ];
+
+    const LOOPS: [Self; 4] = [Self::Loopnz, Self::Loopz, Self::Loop, Self::Jcxz];
+
+    // P = 6 is undocumented on the 8086
+    const SHIFTS: [Self; 8] = [
+        Self::Rol,
+        Self::Ror,
+        Self::Rcl,
+        Self::Rcr,
+        Self::Shl,
+        Self::Shr,
+        Self::Unknown,
+        Self::Sar,
+    ];
+
+    const FPU_ARITHMETIC: [Self; 8] = [
+        Self::Fadd,
+        Self::Fmul,
+        Self::Fcom,
+        Self::Fcomp,
+        Self::Fsub,
+        Self::Fsubr,
+        Self::Fdiv,
+        Self::Fdivr,
+    ];
+
+    const FPU_INTEGER_ARITHMETIC: [Self; 8] = [
+        Self::Fiadd,
+        Self::Fimul,
+        Self::Ficom,
+        Self::Ficomp,
+        Self::Fisub,
+        Self::Fisubr,
+        Self::Fidiv,
+        Self::Fidivr,
+    ];
+
+    const SETS: [Self; 16] = [
+        Self::Seto,
+        Self::Setno,
+        Self::Setb,
+        Self::Setnb,
+        Self::Sete,
+        Self::Setne,
+        Self::Setbe,
+        Self::Seta,
+        Self::Sets,
+        Self::Setns,
+        Self::Setp,
+        Self::Setnp,
+        Self::Setl,
+        Self::Setnl,
+        Self::Setle,
+        Self::Setg,
+    ];
+
+    /// Operation selected by the middle octal digit P of 0P0-0P5 and the xPm group byte.
+    pub fn from_arithmetic_p(p: u8) -> Self {
+        Asm8086::ARITHMETIC[p as usize]
+    }
+
+    /// x87 operation selected by the xPm byte of the 330, 334 memory and 330 register forms.
+    pub fn from_fpu_arithmetic_p(p: u8) -> Self {
+        Asm8086::FPU_ARITHMETIC[p as usize]
+    }
+
+    /// x87 integer operation selected by the xPm byte of the 332 and 336 memory forms.
+    pub fn from_fpu_integer_arithmetic_p(p: u8) -> Self {
+        Asm8086::FPU_INTEGER_ARITHMETIC[p as usize]
+    }
+
+    /// Conditional jump 160+CC, the condition code is the low hexadecimal digit.
+    pub const fn from_cc(cc: u8) -> Self {
+        Asm8086::CONDITIONAL_JUMPS[cc as usize]
+    }
+
+    /// Set byte on condition 017 220+CC, 386 onwards.
+    pub fn from_set_cc(cc: u8) -> Self {
+        Asm8086::SETS[cc as usize]
+    }
+
+    /// The double word form of operations whose mnemonic spells out their operand size.
+    pub fn widened(self) -> Self {
+        use Asm8086::*;
+        match self {
+            Movsw => Movsd,
+            Cmpsw => Cmpsd,
+            Stosw => Stosd,
+            Lodsw => Lodsd,
+            Scasw => Scasd,
+            Insw => Insd,
+            Outsw => Outsd,
+            Cbw => Cwde,
+            Cwd => Cdq,
+            Pusha => Pushad,
+            Popa => Popad,
+            Pushf => Pushfd,
+            Popf => Popfd,
+            Iret => Iretd,
+            _ => self,
+        }
+    }
+
+    /// The quad word form under a REX.W prefix, 64-bit mode only.
+    pub fn widened_to_qword(self) -> Self {
+        use Asm8086::*;
+        match self {
+            Movsw => Movsq,
+            Cmpsw => Cmpsq,
+            Stosw => Stosq,
+            Lodsw => Lodsq,
+            Scasw => Scasq,
+            Cbw => Cdqe,
+            Cwd => Cqo,
+            Iret => Iretq,
+            Pushf => Pushfq,
+            Popf => Popfq,
+            _ => self,
+        }
+    }
+
+    /// Operations whose operand size follows from their meaning, not from the 66 prefix.
+    pub fn keeps_word_size(&self) -> bool {
+        use Asm8086::*;
+        matches!(
+            self,
+            Ret | Retf | Bound | Arpl | Smsw | Lmsw | Lar | Lsl | Enter
+        )
+    }
+
+    /// Shift or rotation selected by the xPm byte of 320-323.
+    pub fn from_shift_p(p: u8) -> Self {
+        Asm8086::SHIFTS[p as usize]
+    }
+
+    pub fn is_shift(&self) -> bool {
+        Asm8086::SHIFTS.contains(self) && *self != Asm8086::Unknown
+    }
+
+    /// Loop family 340-343 selected by the low octal digit.
+    pub fn from_loop(l: u8) -> Self {
+        Asm8086::LOOPS[l as usize]
+    }
+}
+
+impl Display for Asm8086 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Asm8086::*;
+        let text = match *self {
+            Mov => "mov",
+            Add => "add",
+            Or => "or",
+            Adc => "adc",
+            Sbb => "sbb",
+            And => "and",
+            Sub => "sub",
+            Xor => "xor",
+            Cmp => "cmp",
+            Jo => "jo",
+            Jno => "jno",
+            Jb => "jb",
+            Jnb => "jnb",
+            Je => "je",
+            Jne => "jne",
+            Jbe => "jbe",
+            Ja => "ja",
+            Js => "js",
+            Jns => "jns",
+            Jp => "jp",
+            Jnp => "jnp",
+            Jl => "jl",
+            Jnl => "jnl",
+            Jle => "jle",
+            Jg => "jg",
+            Loopnz => "loopnz",
+            Loopz => "loopz",
+            Loop => "loop",
+            Jcxz => "jcxz",
+            Jmp => "jmp",
+            Call => "call",
+            Ret => "ret",
+            Retf => "retf",
+            Int => "int",
+            Int3 => "int3",
+            Into => "into",
+            Iret => "iret",
+            Movsb => "movsb",
+            Movsw => "movsw",
+            Cmpsb => "cmpsb",
+            Cmpsw => "cmpsw",
+            Stosb => "stosb",
+            Stosw => "stosw",
+            Lodsb => "lodsb",
+            Lodsw => "lodsw",
+            Scasb => "scasb",
+            Scasw => "scasw",
+            Push => "push",
+            Pop => "pop",
+            Rol => "rol",
+            Ror => "ror",
+            Rcl => "rcl",
+            Rcr => "rcr",
+            Shl => "shl",
+            Shr => "shr",
+            Sar => "sar",
+            Test => "test",
+            Not => "not",
+            Neg => "neg",
+            Mul => "mul",
+            Imul => "imul",
+            Div => "div",
+            Idiv => "idiv",
+            Inc => "inc",
+            Dec => "dec",
+            Xchg => "xchg",
+            Nop => "nop",
+            Pushf => "pushf",
+            Popf => "popf",
+            Sahf => "sahf",
+            Lahf => "lahf",
+            Cbw => "cbw",
+            Cwd => "cwd",
+            Clc => "clc",
+            Stc => "stc",
+            Cmc => "cmc",
+            Cld => "cld",
+            Std => "std",
+            Cli => "cli",
+            Sti => "sti",
+            Daa => "daa",
+            Das => "das",
+            Aaa => "aaa",
+            Aas => "aas",
+            Aam => "aam",
+            Aad => "aad",
+            Lea => "lea",
+            Lds => "lds",
+            Les => "les",
+            Xlat => "xlat",
+            In => "in",
+            Out => "out",
+            Hlt => "hlt",
+            Wait => "wait",
+            Fadd => "fadd",
+            Fmul => "fmul",
+            Fcom => "fcom",
+            Fcomp => "fcomp",
+            Fsub => "fsub",
+            Fsubr => "fsubr",
+            Fdiv => "fdiv",
+            Fdivr => "fdivr",
+            Fiadd => "fiadd",
+            Fimul => "fimul",
+            Ficom => "ficom",
+            Ficomp => "ficomp",
+            Fisub => "fisub",
+            Fisubr => "fisubr",
+            Fidiv => "fidiv",
+            Fidivr => "fidivr",
+            Faddp => "faddp",
+            Fmulp => "fmulp",
+            Fcompp => "fcompp",
+            Fsubrp => "fsubrp",
+            Fsubp => "fsubp",
+            Fdivrp => "fdivrp",
+            Fdivp => "fdivp",
+            Fld => "fld",
+            Fst => "fst",
+            Fstp => "fstp",
+            Fild => "fild",
+            Fist => "fist",
+            Fistp => "fistp",
+            Fbld => "fbld",
+            Fbstp => "fbstp",
+            Fxch => "fxch",
+            Ffree => "ffree",
+            Fldenv => "fldenv",
+            Fldcw => "fldcw",
+            Fnstenv => "fnstenv",
+            Fnstcw => "fnstcw",
+            Frstor => "frstor",
+            Fnsave => "fnsave",
+            Fnstsw => "fnstsw",
+            Fneni => "fneni",
+            Fndisi => "fndisi",
+            Fnclex => "fnclex",
+            Fninit => "fninit",
+            Fnop => "fnop",
+            Fchs => "fchs",
+            Fabs => "fabs",
+            Ftst => "ftst",
+            Fxam => "fxam",
+            Fld1 => "fld1",
+            Fldl2t => "fldl2t",
+            Fldl2e => "fldl2e",
+            Fldpi => "fldpi",
+            Fldlg2 => "fldlg2",
+            Fldln2 => "fldln2",
+            Fldz => "fldz",
+            F2xm1 => "f2xm1",
+            Fyl2x => "fyl2x",
+            Fptan => "fptan",
+            Fpatan => "fpatan",
+            Fxtract => "fxtract",
+            Fprem1 => "fprem1",
+            Fdecstp => "fdecstp",
+            Fincstp => "fincstp",
+            Fprem => "fprem",
+            Fyl2xp1 => "fyl2xp1",
+            Fsqrt => "fsqrt",
+            Fsincos => "fsincos",
+            Frndint => "frndint",
+            Fscale => "fscale",
+            Fsin => "fsin",
+            Fcos => "fcos",
+            Pusha => "pusha",
+            Popa => "popa",
+            Bound => "bound",
+            Arpl => "arpl",
+            Insb => "insb",
+            Insw => "insw",
+            Outsb => "outsb",
+            Outsw => "outsw",
+            Enter => "enter",
+            Leave => "leave",
+            Sldt => "sldt",
+            Str => "str",
+            Lldt => "lldt",
+            Ltr => "ltr",
+            Verr => "verr",
+            Verw => "verw",
+            Sgdt => "sgdt",
+            Sidt => "sidt",
+            Lgdt => "lgdt",
+            Lidt => "lidt",
+            Smsw => "smsw",
+            Lmsw => "lmsw",
+            Lar => "lar",
+            Lsl => "lsl",
+            Loadall => "loadall",
+            Clts => "clts",
+            Movsd => "movsd",
+            Cmpsd => "cmpsd",
+            Stosd => "stosd",
+            Lodsd => "lodsd",
+            Scasd => "scasd",
+            Insd => "insd",
+            Outsd => "outsd",
+            Cwde => "cwde",
+            Cdq => "cdq",
+            Pushad => "pushad",
+            Popad => "popad",
+            Pushfd => "pushfd",
+            Popfd => "popfd",
+            Iretd => "iretd",
+            Jecxz => "jecxz",
+            Movzx => "movzx",
+            Movsx => "movsx",
+            Seto => "seto",
+            Setno => "setno",
+            Setb => "setb",
+            Setnb => "setnb",
+            Sete => "sete",
+            Setne => "setne",
+            Setbe => "setbe",
+            Seta => "seta",
+            Sets => "sets",
+            Setns => "setns",
+            Setp => "setp",
+            Setnp => "setnp",
+            Setl => "setl",
+            Setnl => "setnl",
+            Setle => "setle",
+            Setg => "setg",
+            Cdqe => "cdqe",
+            Cqo => "cqo",
+            Movsq => "movsq",
+            Cmpsq => "cmpsq",
+            Stosq => "stosq",
+            Lodsq => "lodsq",
+            Scasq => "scasq",
+            Iretq => "iretq",
+            Pushfq => "pushfq",
+            Popfq => "popfq",
+            Jrcxz => "jrcxz",
+            Movsxd => "movsxd",
+            Endbr64 => "endbr64",
+            Db => "db",
+            Unknown => "unknown",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Repeat {
+    Rep,   // 0o363, also repe / repz
+    Repne, // 0o362, also repnz
+}
+
+/// Prefix bytes that were folded into the instruction following them.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Prefixes {
+    pub lock: bool,
+    pub operand_size: bool, // 0o146, flips between 16 and 32-bit words
+    pub address_size: bool, // 0o147, flips between 16 and 32-bit addresses
+    pub rex: Option<u8>,    // 0o100-0o117 in 64-bit mode, the low digits are W R X B
+    pub repeat: Option<Repeat>,
+    pub segment: Option<SegmentRegister>,
+}
+
+/// One instruction decoded from the byte stream together with where it was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodedInstruction {
+    pub prefixes: Prefixes,
+    pub op: Asm8086,
+    pub operands: Operands,
+    pub offset: usize,
+    pub length: usize,
+}
+
+impl DecodedInstruction {
+    /// Absolute offset a relative jump lands on, `None` when it leaves the address space.
+    pub fn jump_target(&self) -> Option<usize> {
+        let disp = self.operands.iter().find_map(|operand| match operand {
+            Operand::C(disp) => disp.value(),
+            _ => None,
+        })?;
+        let next = (self.offset + self.length) as isize;
+        usize::try_from(next + disp).ok()
+    }
+
+    /// The repeat prefix as written in front of the mnemonic, `repe` for the comparing string ops.
+    pub fn repeat_text(&self) -> Option<&'static str> {
+        let compares = matches!(
+            self.op,
+            Asm8086::Cmpsb
+                | Asm8086::Cmpsw
+                | Asm8086::Cmpsd
+                | Asm8086::Cmpsq
+                | Asm8086::Scasb
+                | Asm8086::Scasw
+                | Asm8086::Scasd
+                | Asm8086::Scasq
+        );
+        match (self.prefixes.repeat?, compares) {
+            (Repeat::Rep, false) => Some("rep"),
+            (Repeat::Rep, true) => Some("repe"),
+            (Repeat::Repne, _) => Some("repne"),
+        }
+    }
+
+    /// Memory operands need an explicit `byte`/`word` when no register tells the size.
+    /// The CL count of a shift says nothing about the size of the shifted operand,
+    /// and neither does the target of a zero or sign extension about its source.
+    pub(crate) fn needs_size(&self) -> bool {
+        self.op.is_shift()
+            || matches!(self.op, Asm8086::Movzx | Asm8086::Movsx)
+            || !self.operands.iter().any(|operand| operand.is_register())
+    }
+}
+
+impl Display for DecodedInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Syntax::Nasm.format(self))
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::instruction::DecodedInstruction;
+use crate::syntax::{Formatted, Syntax};
+
+/// `label_N` names for the jump targets of a decoded stream, numbered in address order.
+///
+/// Only targets that land on an instruction boundary (or right after the last
+/// instruction) get a name, everything else keeps the `$+N` form.
+#[derive(Debug, Default)]
+pub struct Labels {
+    names: BTreeMap<usize, usize>,
+}
+
+impl Labels {
+    pub fn collect(instructions: &[DecodedInstruction]) -> Self {
+        let end = instructions
+            .last()
+            .map(|instruction| instruction.offset + instruction.length)
+            .unwrap_or(0);
+        let is_boundary = |target: &usize| {
+            *target == end
+                || instructions
+                    .binary_search_by_key(target, |instruction| instruction.offset)
+                    .is_ok()
+        };
+        let targets: BTreeSet<usize> = instructions
+            .iter()
+            .filter_map(|instruction| instruction.jump_target())
+            .filter(is_boundary)
+            .collect();
+        let names = targets
+            .into_iter()
+            .enumerate()
+            .map(|(n, target)| (target, n))
+            .collect();
+        Labels { names }
+    }
+
+    pub fn name(&self, offset: usize) -> Option<String> {
+        self.names.get(&offset).map(|n| format!("label_{}", n))
+    }
+
+    /// Displays `instruction` with its jump target replaced by the label name.
+    pub fn apply<'a>(&self, instruction: &'a DecodedInstruction) -> Formatted<'a> {
+        self.apply_for(instruction, Syntax::Nasm)
+    }
+
+    /// Like `apply`, written in `syntax`.
+    pub fn apply_for<'a>(
+        &self,
+        instruction: &'a DecodedInstruction,
+        syntax: Syntax,
+    ) -> Formatted<'a> {
+        let label = instruction
+            .jump_target()
+            .and_then(|target| self.name(target));
+        syntax.format(instruction).with_label(label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode;
+
+    fn disassemble(bytes: &[u8]) -> Vec<String> {
+        let instructions = decode(bytes).unwrap();
+        let labels = Labels::collect(&instructions);
+        let mut lines = Vec::new();
+        for instruction in instructions.iter() {
+            if let Some(label) = labels.name(instruction.offset) {
+                lines.push(format!("{}:", label));
+            }
+            lines.push(labels.apply(instruction).to_string());
+        }
+        lines
+    }
+
+    #[test]
+    fn names_targets_in_address_order() {
+        // jne +2 / jne -4 / jne -6
+        let lines = disassemble(&[0o165, 0o002, 0o165, 0o374, 0o165, 0o372]);
+        assert_eq!(
+            lines,
+            vec![
+                "label_0:",
+                "jne label_1",
+                "jne label_0",
+                "label_1:",
+                "jne label_0"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_relative_form_for_targets_inside_instructions() {
+        let lines = disassemble(&[0o271, 0o001, 0o000, 0o353, 0o372]);
+        assert_eq!(lines, vec!["mov cx, 1", "jmp $-4"]);
+    }
+
+    #[test]
+    fn can_label_near_calls() {
+        // call +1 / ret / ret
+        let lines = disassemble(&[0o350, 0o001, 0o000, 0o303, 0o303]);
+        assert_eq!(lines, vec!["call label_0", "ret", "label_0:", "ret"]);
+    }
+
+    #[test]
+    fn can_label_listing_0041_loops() {
+        let lines = disassemble(include_bytes!("../listing_0041_add_sub_cmp_jnz.bin"));
+        let tail = &lines[lines.len() - 5..];
+        assert_eq!(
+            tail,
+            [
+                "jns label_2",
+                "loop label_2",
+                "loopz label_2",
+                "loopnz label_2",
+                "jcxz label_2"
+            ]
+        );
+        assert!(lines.contains(&String::from("label_2:")));
+    }
+
+    #[test]
+    fn can_label_att_jumps() {
+        let instructions = decode(&[0o165, 0o376, 0o353, 0o000]).unwrap();
+        let labels = Labels::collect(&instructions);
+        let lines: Vec<String> = instructions
+            .iter()
+            .map(|instruction| labels.apply_for(instruction, Syntax::Att).to_string())
+            .collect();
+        assert_eq!(lines, vec!["jne label_0", "jmp label_1"]);
+    }
+}
@@ -1,2 +1,10 @@
 pub mod bytes_io;
-pub mod state_machine;
\ No newline at end of file
+pub mod decoder;
+pub mod elf;
+pub mod export;
+pub mod instruction;
+pub mod labels;
+pub mod listing;
+mod opcode_table;
+pub mod state_machine;
+pub mod syntax;
@@ -0,0 +1,234 @@
+//! objdump-like listing: the address, the raw bytes in a chosen radix and the
+//! instruction text, one aligned row per instruction.
+//!
+//! ```text
+//! 00000000  211 331                  mov cx, bx
+//! ```
+
+use std::fmt::Write;
+
+use crate::decoder::Bits;
+
+/// Radix the raw bytes are written in. Binary matches `xxd -b`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    #[default]
+    Octal,
+    Hex,
+    Binary,
+}
+
+impl std::str::FromStr for Radix {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "octal" | "oct" | "8" => Ok(Radix::Octal),
+            "hex" | "16" => Ok(Radix::Hex),
+            "binary" | "bin" | "2" => Ok(Radix::Binary),
+            _ => Err(format!("Unknown radix '{}'", text)),
+        }
+    }
+}
+
+impl Radix {
+    /// Digits of one byte, every byte takes the same width.
+    pub fn digits(self) -> usize {
+        match self {
+            Radix::Octal => 3,
+            Radix::Hex => 2,
+            Radix::Binary => 8,
+        }
+    }
+
+    pub fn format_byte(self, byte: u8) -> String {
+        match self {
+            Radix::Octal => format!("{:03o}", byte),
+            Radix::Hex => format!("{:02x}", byte),
+            Radix::Binary => format!("{:08b}", byte),
+        }
+    }
+}
+
+/// Hex digits of a 64-bit address, the widest address column that makes sense.
+pub const MAX_ADDRESS_WIDTH: usize = 16;
+
+/// Widest byte column, no instruction is longer than 15 bytes anyway.
+pub const MAX_BYTES_PER_LINE: usize = 16;
+
+/// Layout of the listing columns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listing {
+    pub radix: Radix,
+    /// Address the first decoded byte is loaded at, `0x100` for a `.com` file.
+    pub origin: u64,
+    /// Hex digits of the address column.
+    pub address_width: usize,
+    /// Bytes in the byte column, longer instructions continue on the next rows.
+    pub bytes_per_line: usize,
+    /// Width of the addresses, they wrap around at its top.
+    pub bits: Bits,
+}
+
+impl Default for Listing {
+    fn default() -> Self {
+        Listing {
+            radix: Radix::default(),
+            origin: 0,
+            address_width: 8,
+            bytes_per_line: 6,
+            bits: Bits::default(),
+        }
+    }
+}
+
+impl Listing {
+    /// Address of `offset`, wrapping around at 64 KiB in 16-bit code the way the
+    /// instruction pointer does, and at 4 GiB and 2^64 in 32 and 64-bit code.
+    fn address(&self, offset: usize) -> u64 {
+        let address = self.origin.wrapping_add(offset as u64);
+        match self.bits {
+            Bits::Sixteen => address & 0xffff,
+            Bits::ThirtyTwo => address & 0xffff_ffff,
+            Bits::SixtyFour => address,
+        }
+    }
+
+    fn bytes_width(&self) -> usize {
+        let per_line = self.bytes_per_line.max(1);
+        per_line * self.radix.digits() + per_line - 1
+    }
+
+    /// Rows of the instruction at `offset`: the address, its `bytes` and `text` on
+    /// the first one, the bytes that do not fit on the rows after it.
+    pub fn lines(&self, offset: usize, bytes: &[u8], text: &str) -> Vec<String> {
+        let per_line = self.bytes_per_line.max(1);
+        // instructions always have bytes, the empty row only keeps the text
+        let chunks: Vec<&[u8]> = if bytes.is_empty() {
+            vec![&[]]
+        } else {
+            bytes.chunks(per_line).collect()
+        };
+        let mut lines = Vec::new();
+        for (n, chunk) in chunks.into_iter().enumerate() {
+            let address = self.address(offset + n * per_line);
+            let dump: Vec<String> = chunk
+                .iter()
+                .map(|&byte| self.radix.format_byte(byte))
+                .collect();
+            let dump = dump.join(" ");
+            let mut line = format!("{:0width$x}  {}", address, dump, width = self.address_width);
+            if n == 0 {
+                let pad = self.bytes_width().saturating_sub(dump.len());
+                write!(line, "{:pad$}  {}", "", text, pad = pad).expect("unable to write listing");
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Row of a label placed at `offset`, for the jump targets of `labels`.
+    pub fn label(&self, offset: usize, name: &str) -> String {
+        let address = self.address(offset);
+        format!(
+            "{:0width$x}  <{}>:",
+            address,
+            name,
+            width = self.address_width
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_list_in_every_radix() {
+        let bytes = [0o211, 0o331];
+        let octal = Listing::default();
+        assert_eq!(
+            octal.lines(0, &bytes, "mov cx, bx"),
+            vec!["00000000  211 331                  mov cx, bx"]
+        );
+        let hex = Listing {
+            radix: Radix::Hex,
+            origin: 0x100,
+            address_width: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            hex.lines(2, &bytes, "mov cx, bx"),
+            vec!["0102  89 d9              mov cx, bx"]
+        );
+        let binary = Listing {
+            radix: Radix::Binary,
+            bytes_per_line: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            binary.lines(0, &bytes, "mov cx, bx"),
+            vec!["00000000  10001001 11011001  mov cx, bx"]
+        );
+    }
+
+    #[test]
+    fn can_wrap_long_instructions() {
+        // mov word [bx + si + 4], 7 with two bytes per row
+        let bytes = [0o307, 0o100, 0o004, 0o007, 0o000];
+        let listing = Listing {
+            radix: Radix::Hex,
+            address_width: 4,
+            bytes_per_line: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            listing.lines(16, &bytes, "mov word [bx + si + 4], 7"),
+            vec![
+                "0010  c7 40  mov word [bx + si + 4], 7",
+                "0012  04 07",
+                "0014  00"
+            ]
+        );
+    }
+
+    #[test]
+    fn wraps_addresses_past_the_origin() {
+        let listing = Listing {
+            radix: Radix::Hex,
+            origin: u64::MAX,
+            address_width: 16,
+            bytes_per_line: 1,
+            bits: Bits::SixtyFour,
+        };
+        assert_eq!(
+            listing.lines(0, &[0o220, 0o220], "nop"),
+            vec!["ffffffffffffffff  90  nop", "0000000000000000  90"]
+        );
+        assert_eq!(listing.label(2, "label_0"), "0000000000000001  <label_0>:");
+        let com = Listing {
+            origin: 0xffff,
+            address_width: 4,
+            bits: Bits::Sixteen,
+            ..listing
+        };
+        assert_eq!(
+            com.lines(0, &[0o220, 0o220], "nop"),
+            vec!["ffff  90  nop", "0000  90"]
+        );
+        let flat = Listing {
+            origin: 0xffff_ffff,
+            address_width: 8,
+            bits: Bits::ThirtyTwo,
+            ..listing
+        };
+        assert_eq!(flat.label(1, "label_0"), "00000000  <label_0>:");
+    }
+
+    #[test]
+    fn can_parse_radix_names() {
+        assert_eq!("hex".parse(), Ok(Radix::Hex));
+        assert_eq!("binary".parse(), Ok(Radix::Binary));
+        assert!("decimal".parse::<Radix>().is_err());
+    }
+}
@@ -1,381 +1,198 @@
-use std::fmt::{Display};
 use asm8086_octal::bytes_io;
-
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ByteRegister {
-    AL,
-    CL,
-    DL,
-    BL,
-    AH,
-    CH,
-    DH,
-    BH,
-}
-
-use ByteRegister::*;
-impl ByteRegister {
-    const VALUES: [Self; 8] = [AL, CL, DL, BL, AH, CH, DH, BH];
-    fn from_r(r: u8) -> Self {
-        ByteRegister::VALUES[r as usize]
+use asm8086_octal::decoder::{self, Bits, Cpu, Mode};
+use asm8086_octal::elf::{self, Elf};
+use asm8086_octal::export::{self, Format};
+use asm8086_octal::instruction::Asm8086;
+use asm8086_octal::labels::Labels;
+use asm8086_octal::listing::{self, Listing};
+use asm8086_octal::syntax::Syntax;
+
+/// Width option `--name=text` checked to lie in `1..=max`.
+fn column_width(name: &str, text: &str, max: usize) -> Result<usize, String> {
+    match bytes_io::parse_number(text)? {
+        width if (1..=max as u64).contains(&width) => Ok(width as usize),
+        _ => Err(format!("--{} must be between 1 and {}", name, max)),
     }
 }
 
-impl Display for ByteRegister {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let text = match *self {
-            AL => "al",
-            CL => "cl",
-            DL => "dl",
-            BL => "bl",
-            AH => "ah",
-            CH => "ch",
-            DH => "dh",
-            BH => "bh",
-        };
-        write!(f, "{}", text)
+/// Listing columns from `--radix=`, `--origin=`, `--address-width=` and `--bytes-per-line=`,
+/// looked up with `option` so the checks can be tested without a command line.
+fn listing_cli(
+    option: impl Fn(&str) -> Option<String>,
+    origin: u64,
+    bits: Bits,
+) -> Result<Listing, String> {
+    let mut listing = Listing {
+        origin,
+        bits,
+        ..Listing::default()
+    };
+    if let Some(radix) = option("radix") {
+        listing.radix = radix.parse()?;
     }
-}
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum WordRegister {
-    AX,
-    CX,
-    DX,
-    BX,
-    SP,
-    BP,
-    SI,
-    DI,
-}
-
-use WordRegister::*;
-impl WordRegister {
-    const VALUES: [Self; 8] = [AX, CX, DX, BX, SP, BP, SI, DI];
-    fn from_r(r: u8) -> Self {
-        WordRegister::VALUES[r as usize]
+    if let Some(origin) = option("origin") {
+        listing.origin = bytes_io::parse_number(&origin)?;
     }
-}
-
-impl Display for WordRegister {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let text = match *self {
-            AX => "ax",
-            CX => "cx",
-            DX => "dx",
-            BX => "bx",
-            SP => "sp",
-            BP => "bp",
-            SI => "si",
-            DI => "di",
-        };
-        write!(f, "{}", text)
-    }
-}
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Pointer {
-    BX_SI(Disp),
-    BX_DI(Disp),
-    BP_SI(Disp),
-    BP_DI(Disp),
-    SI(Disp),
-    DI(Disp),
-    Direct(Disp),
-    BP(Disp),
-    BX(Disp),
-    Unread,
-}
-
-impl Pointer {
-    fn with_disp(r: u8, disp: Disp) -> Self {
-        match r {
-            0 => Pointer::BX_SI(disp),
-            1 => Pointer::BX_DI(disp),
-            2 => Pointer::BP_SI(disp),
-            3 => Pointer::BP_DI(disp),
-            4 => Pointer::SI(disp),
-            5 => Pointer::DI(disp),
-            6 => Pointer::BP(disp),
-            7 => Pointer::BX(disp),
-            _ => Pointer::Unread,
-        }
+    if let Some(width) = option("address-width") {
+        listing.address_width = column_width("address-width", &width, listing::MAX_ADDRESS_WIDTH)?;
     }
-
-    fn direct(disp: Disp) -> Self {
-        Pointer::Direct(disp)
+    if let Some(width) = option("bytes-per-line") {
+        listing.bytes_per_line =
+            column_width("bytes-per-line", &width, listing::MAX_BYTES_PER_LINE)?;
     }
+    Ok(listing)
 }
 
-impl Display for Pointer {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Pointer::*;
-        match *self {
-            BX_SI(disp) => write!(f, "[bx + si{}]", disp),
-            BX_DI(disp) => write!(f, "[bx + di{}]", disp),
-            BP_SI(disp) => write!(f, "[bp + si{}]", disp),
-            BP_DI(disp) => write!(f, "[bp + di{}]", disp),
-            SI(disp) => write!(f, "[sp{}]", disp),
-            DI(disp) => write!(f, "[di{}]", disp),
-            Direct(disp) => write!(f, "[{}]", disp),
-            BP(disp) => write!(f, "[bp{}]", disp),
-            BX(disp) => write!(f, "[bx{}]", disp),
-            Unread => write!(f, "Unread"),
+fn main() -> Result<(), String> {
+    let file_bytes = bytes_io::read_bytes_cli()?;
+    let is_elf = elf::is_elf(&file_bytes);
+    let (bytes, address) = if is_elf {
+        let elf = Elf::parse(&file_bytes)?;
+        let code = match bytes_io::option_cli("function") {
+            Some(name) => elf.function(&name)?,
+            None => elf.text()?,
+        };
+        (code.bytes.to_vec(), code.address)
+    } else {
+        (file_bytes, 0)
+    };
+    let cpu = match bytes_io::option_cli("cpu") {
+        Some(cpu) => cpu.parse()?,
+        None => Cpu::default(),
+    };
+    let bits: Bits = match bytes_io::option_cli("bits") {
+        Some(bits) => bits.parse()?,
+        None if is_elf => Bits::SixtyFour,
+        None => Bits::default(),
+    };
+    let mode = Mode::new(cpu, bits);
+    let syntax: Syntax = match bytes_io::option_cli("syntax") {
+        Some(syntax) => syntax.parse()?,
+        None => Syntax::default(),
+    };
+    let format: Format = match bytes_io::option_cli("format") {
+        Some(format) => format.parse()?,
+        None => Format::default(),
+    };
+    let (instructions, regions) = if bytes_io::flag_cli("resync") {
+        decoder::decode_resync(&bytes, mode)
+    } else {
+        let instructions = decoder::decode_for(&bytes, mode).map_err(|error| error.to_string())?;
+        (instructions, Vec::new())
+    };
+    let labels = Labels::collect(&instructions);
+    if format == Format::Listing {
+        let listing = listing_cli(bytes_io::option_cli, address, mode.bits)?;
+        for instruction in instructions.iter() {
+            if let Some(label) = labels.name(instruction.offset) {
+                println!("{}", listing.label(instruction.offset, &label));
+            }
+            let code = &bytes[instruction.offset..instruction.offset + instruction.length];
+            let text = labels.apply_for(instruction, syntax).to_string();
+            for line in listing.lines(instruction.offset, code, &text) {
+                println!("{}", line);
+            }
         }
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Address {
-    ByteRegister(ByteRegister),
-    WordRegister(WordRegister),
-    Pointer(Pointer),
-    ByteRegisterUnread,
-    WordRegisterUnread,
-    PointerUnread,
-    Unread,
-}
-
-impl Display for Address {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Address::*;
-        match *self {
-            ByteRegister(register) => write!(f, "{}", register),
-            WordRegister(register) => write!(f, "{}", register),
-            Pointer(pointer) => write!(f, "{}", pointer),
-            _ => write!(f, "{:?}", self),
+        if let Some(label) = labels.name(bytes.len()) {
+            println!("{}", listing.label(bytes.len(), &label));
         }
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Operand {
-    Rb(Address),
-    Rw(Address),
-    Eb(Address), // effective address byte
-    Ew(Address), // effective word
-    D(Disp),
-    SR, // segment register
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Disp {
-    None,
-    D8(i8),
-    D16(i16),
-    D8Unread,
-    D16Unread
-}
-
-impl Display for Disp {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Disp::*;
-        match *self {
-            None | D8(0) | D16(0) => write!(f, ""),
-            D8(x) if x < 0 => write!(f, " - {}", -x),
-            D16(x) if x < 0 => write!(f, " - {}", -x),
-            D8(x) => write!(f, " + {}", x),
-            D16(x) => write!(f, " + {}", x),
-            _ => write!(f, "{:?}", self),
+        for region in regions.iter() {
+            println!("{} {}", syntax.comment(), region);
         }
+        return Ok(());
     }
-}
-
-impl Display for Operand {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use Operand::*;
-        match *self {
-            Rb(inner) => write!(f, "{}", inner),
-            Rw(inner) => write!(f, "{}", inner),
-            Eb(inner) => write!(f, "{}", inner),
-            Ew(inner) => write!(f, "{}", inner),
-            _ => write!(f, "{:?}", self),
+    if format != Format::Text {
+        if format == Format::Csv {
+            println!("{}", export::csv_header());
+        }
+        for instruction in instructions.iter() {
+            let code = &bytes[instruction.offset..instruction.offset + instruction.length];
+            let text = syntax.format(instruction).to_string();
+            match format {
+                Format::Csv => println!("{}", export::csv(instruction, code, &text)),
+                _ => println!("{}", export::json(instruction, code, &text)),
+            }
         }
+        return Ok(());
     }
-}
-
-#[derive(Debug)]
-enum Asm8086 {
-    Mov(Operand, Operand),
-    Add(Operand, Operand),
-    Sub(Operand, Operand),
-    Cmp(Operand, Operand),
-    Unknown,
-}
-
-
-enum Type {
-    BetweenRegisters
-}
-
-fn opcode_to_instruction(opcode_byte: u8) -> Asm8086 {
-    use ByteRegister::*;
-    use Operand::*;
-    use WordRegister::*;
-    match opcode_byte {
-        0o000 => Asm8086::Add(Eb(Address::ByteRegisterUnread), Rb(Address::ByteRegisterUnread)),
-        0o001 => Asm8086::Add(Ew(Address::WordRegisterUnread), Rw(Address::WordRegisterUnread)),
-        0o002 => Asm8086::Add(Rb(Address::ByteRegisterUnread), Eb(Address::ByteRegisterUnread)),
-        0o003 => Asm8086::Add(Rw(Address::WordRegisterUnread), Ew(Address::WordRegisterUnread)),
-        0o210 => Asm8086::Mov(
-            Eb(Address::ByteRegisterUnread),
-            Rb(Address::ByteRegisterUnread),
-        ),
-        0o211 => Asm8086::Mov(
-            Ew(Address::WordRegisterUnread),
-            Rw(Address::WordRegisterUnread),
-        ),
-        0o212 => Asm8086::Mov(
-            Rb(Address::ByteRegisterUnread),
-            Eb(Address::ByteRegisterUnread),
-        ),
-        0o213 => Asm8086::Mov(
-            Rw(Address::WordRegisterUnread),
-            Ew(Address::WordRegisterUnread),
-        ),
-        0o214 => Asm8086::Mov(Ew(Address::WordRegisterUnread), SR),
-        0o216 => Asm8086::Mov(SR, Ew(Address::WordRegisterUnread)),
-        // direct from / to accumulator
-        0o240 => Asm8086::Mov(Eb(Address::ByteRegister(AL)), D(Disp::D16Unread)),
-        0o241 => Asm8086::Mov(Ew(Address::WordRegister(AX)), D(Disp::D16Unread)),
-        0o242 => Asm8086::Mov(D(Disp::D16Unread), Eb(Address::ByteRegister(AL))),
-        0o243 => Asm8086::Mov(D(Disp::D16Unread), Eb(Address::WordRegister(AX))),
-        // Direct to byte register 0o26r-Db
-        0o261 => Asm8086::Mov(Rb(Address::ByteRegister(CL)), D(Disp::D8Unread)),
-        0o265 => Asm8086::Mov(Rb(Address::ByteRegister(CH)), D(Disp::D8Unread)),
-        // Direct to word register 0o27r-Dw
-        0o271 => Asm8086::Mov(Rw(Address::WordRegister(CX)), D(Disp::D16Unread)),
-        0o272 => Asm8086::Mov(Rw(Address::WordRegister(DX)), D(Disp::D16Unread)),
-        _ => Asm8086::Unknown,
+    // everything but the instructions is a comment, so the output assembles back
+    let comment = syntax.comment();
+    if let Some(header) = syntax.header(bits) {
+        println!("{header}");
     }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Mod {
-    MemoryNoDisp,
-    Memory8BitDisp,
-    Memory16BitDisp,
-    Register,
-}
-
-fn byte_octals(byte: u8) -> (u8, u8, u8) {
-    let first = (byte & 0b11000000) >> 6;
-    let second = (byte & 0b00111000) >> 3;
-    let third = byte & 0b00000111;
-    (first, second, third)
-}
-
-fn resolve_mov_operands(byte: u8) -> (Mod, u8, u8) {
-    let (x, r_or_s, m) = byte_octals(byte);
-    let mode = match x {
-        0 => Mod::MemoryNoDisp,
-        1 => Mod::Memory8BitDisp,
-        2 => Mod::Memory16BitDisp,
-        _ => Mod::Register,
-    };
-    (mode, r_or_s, m)
-}
-
-fn resolve_address(operand: Operand, mode: Mod, r_or_s: u8, m: u8, disp: Disp) -> Address {
-    use Operand::*;
-    match (operand, mode, m) {
-        (_, Mod::MemoryNoDisp, 6) => Address::Pointer(Pointer::direct(disp)),
-        (Rb(_), _, _) => Address::ByteRegister(ByteRegister::from_r(r_or_s)),
-        (Rw(_), _, _) => Address::WordRegister(WordRegister::from_r(r_or_s)),
-        (Eb(_), Mod::MemoryNoDisp, _) => Address::Pointer(Pointer::with_disp(m, disp)),
-        (Ew(_), Mod::MemoryNoDisp, _) => Address::Pointer(Pointer::with_disp(m, disp)),
-        (Eb(_), Mod::Memory8BitDisp | Mod::Memory16BitDisp, _) => {
-            Address::Pointer(Pointer::with_disp(m, disp))
+    for instruction in instructions.iter() {
+        let start_ptr = instruction.offset;
+        let end_ptr = instruction.offset + instruction.length;
+        if let Some(label) = labels.name(start_ptr) {
+            println!("{label}:");
         }
-        (Ew(_), Mod::Memory8BitDisp | Mod::Memory16BitDisp, _) => {
-            Address::Pointer(Pointer::with_disp(m, disp))
+        match instruction.op {
+            Asm8086::Unknown => println!(
+                "{} unable to parse opcode bit {:#o}",
+                comment, bytes[start_ptr]
+            ),
+            _ => println!(
+                "{}",
+                labels
+                    .apply_for(instruction, syntax)
+                    .with_encoding(&bytes[start_ptr..end_ptr], mode)
+            ),
         }
-        (Eb(_), Mod::Register, _) => Address::ByteRegister(ByteRegister::from_r(m)),
-        (Ew(_), Mod::Register, _) => Address::WordRegister(WordRegister::from_r(m)),
-        _ => Address::Unread,
+        let parsed_bytes = bytes_io::format_bytes(&bytes, start_ptr, end_ptr);
+        println!(
+            "{} bytes {}..{} = {}",
+            comment, start_ptr, end_ptr, parsed_bytes
+        );
     }
+    if let Some(label) = labels.name(bytes.len()) {
+        println!("{label}:");
+    }
+    for region in regions.iter() {
+        println!("{} {}", comment, region);
+    }
+    Ok(())
 }
 
-fn to_word(low_byte: u8, high_byte: u8) -> i16 {
-    ((high_byte as i16) << 8) | (low_byte as i16)
-}
-
-
-fn next_byte_disp(bytes: &[u8], end_ptr: usize) -> Result<Disp, String> {
-    let low_byte = *bytes.get(end_ptr).ok_or("could not parse byte")?;
-    Ok(Disp::D8(low_byte as i8))
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn next_word_disp(bytes: &[u8], end_ptr: usize) -> Result<Disp, String> {
-    let low_byte = *bytes.get(end_ptr).ok_or("could not parse byte")?;
-    let high_byte = *bytes.get(end_ptr + 1).ok_or("could not parse byte")?;
-    let disp_word = to_word(low_byte, high_byte);
-    Ok(Disp::D16(disp_word))
-}
+    fn listing_for(options: &[(&str, &str)]) -> Result<Listing, String> {
+        let option = |name: &str| {
+            options
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        };
+        listing_cli(option, 0, Bits::Sixteen)
+    }
 
+    #[test]
+    fn can_read_listing_options() {
+        let listing = listing_for(&[
+            ("radix", "hex"),
+            ("origin", "0x7c00"),
+            ("address-width", "4"),
+            ("bytes-per-line", "8"),
+        ])
+        .unwrap();
+        assert_eq!(listing.origin, 0x7c00);
+        assert_eq!(listing.address_width, 4);
+        assert_eq!(listing.bytes_per_line, 8);
+    }
 
-fn parse_bytes(bytes: &[u8]) -> Result<(), String> {
-    let mut start_ptr = 0;
-    let mut end_ptr = 0;
-        while start_ptr != bytes.len() {
-        let first_byte = bytes[end_ptr];
-        end_ptr += 1;
-        let opcode = opcode_to_instruction(first_byte);
-        match opcode {
-            Asm8086::Mov(reg, Operand::D(Disp::D8Unread)) => {
-                let disp = next_byte_disp(bytes, end_ptr)?;
-                end_ptr += 1;
-                println!("mov {reg}, [{disp}]")
-            }
-            Asm8086::Mov(reg, Operand::D(Disp::D16Unread)) => {
-                let disp = next_word_disp(bytes, end_ptr)?;
-                end_ptr += 2;
-                println!("mov {reg}, [{disp}]")
-            },
-            Asm8086::Mov(Operand::D(Disp::D16Unread), reg) => {
-                let disp = next_word_disp(bytes, end_ptr)?;
-                end_ptr += 2;
-                println!("mov [{disp}], {reg}")
-            }
-            Asm8086::Mov(dest, src) => {
-                let second_byte = bytes[end_ptr];
-                end_ptr += 1;
-                let (mode, r_or_s, m) = resolve_mov_operands(second_byte);
-                let disp = match (mode, m) {
-                    (Mod::MemoryNoDisp, 6) | (Mod::Memory16BitDisp, _) => {
-                        let disp = next_word_disp(bytes, end_ptr)?;
-                        end_ptr += 2;
-                        disp
-                    }
-                    (Mod::Memory8BitDisp, _) => {
-                        let disp = next_byte_disp(bytes, end_ptr)?;
-                        end_ptr += 1;
-                        disp
-                    }
-                    _ => {
-                        Disp::None
-                    }
-                };
-                let src = resolve_address(src, mode, r_or_s, m, disp);
-                let dest = resolve_address(dest, mode, r_or_s, m, disp);
-                println!("mov {dest}, {src}");
-            }
-            _ => println!("unable to parse opcode bit {first_byte:#o}"),
+    #[test]
+    fn rejects_bad_listing_options() {
+        for options in [
+            [("address-width", "17")],
+            [("address-width", "0")],
+            [("address-width", "18446744073709551615")],
+            [("bytes-per-line", "0")],
+            [("bytes-per-line", "100000")],
+            [("origin", "0x1_0000_0000_0000_0000")],
+            [("radix", "decimal")],
+        ] {
+            assert!(listing_for(&options).is_err(), "{:?}", options);
         }
-
-        let parsed_bytes = bytes_io::format_bytes(&bytes, start_ptr, end_ptr);
-        println!("bytes {}..{} = {}", start_ptr, end_ptr, parsed_bytes);
-        start_ptr = end_ptr;
     }
-    Ok(())
-}
-    
-fn main() -> Result<(), String> {
-    let bytes = bytes_io::read_bytes_cli()?;
-    //let bytes = vec![0o213, 0o56, 0o5, 0o0]; // mov bp, 5
-    //let bytes = vec![0o241, 0o373, 0o11]; // mov ax, [2555]
-
-    let bytes = vec![0o260, 0o001]; // mov al, [1]
-    let bytes = vec![0o271, 0o001, 0o000]; // mov cx, [1]
-    parse_bytes(&bytes)?;
-    Ok(())
 }
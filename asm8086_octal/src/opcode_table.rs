@@ -0,0 +1,88 @@
+//! One byte opcodes generated by `build.rs` from the opcode summary of octal_x86.txt.
+
+use crate::decoder::{Bits, Cpu};
+use crate::instruction::{
+    Address, Asm8086, ByteRegister, Disp, Operand, Operands, Pointer, SegmentRegister, WordRegister,
+};
+
+/// Mnemonic and operand templates of an opcode byte and the first cpu that has it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpcodeTemplate {
+    pub op: Asm8086,
+    pub operands: Operands,
+    pub cpu: Cpu,
+    /// r digit the mod rm byte needs, for the one member groups like `306 x0m`.
+    /// The 8086 ignores it, later cpus refuse the other digits.
+    pub member: Option<u8>,
+    /// Whether a mod rm byte follows the opcode.
+    pub mod_rm: bool,
+    /// Immediate data after the mod rm byte, for finding lengths without the operands.
+    pub immediate: &'static [Immediate],
+}
+
+/// One part of the immediate data of an opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Immediate {
+    Byte,
+    /// Word or double word with the operand size.
+    Word,
+    /// Word whatever the operand size, counts and segments.
+    FixedWord,
+    /// Direct address of the address size.
+    Address,
+}
+
+impl Immediate {
+    /// Bytes of the part, `word` being 2 or 4 with the operand size.
+    pub fn length(self, word: usize, address_size: Bits) -> usize {
+        match (self, address_size) {
+            (Immediate::Byte, _) => 1,
+            (Immediate::Word, _) => word,
+            (Immediate::FixedWord, _) | (Immediate::Address, Bits::Sixteen) => 2,
+            (Immediate::Address, Bits::ThirtyTwo) => 4,
+            (Immediate::Address, Bits::SixtyFour) => 8,
+        }
+    }
+}
+
+/// Indexed by the opcode byte, `None` for prefixes, group opcodes, the 0o017
+/// escape on later cpus and bytes the reference leaves out.
+pub static OPCODE_TABLE: [Option<OpcodeTemplate>; 256] =
+    include!(concat!(env!("OUT_DIR"), "/opcode_table.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_the_reference() {
+        let mov = OPCODE_TABLE[0o210].unwrap();
+        assert_eq!(mov.op, Asm8086::Mov);
+        assert_eq!(
+            mov.operands,
+            Operands::Two(
+                Operand::Eb(Address::ByteRegisterUnread),
+                Operand::Rb(Address::ByteRegisterUnread)
+            )
+        );
+        assert_eq!(OPCODE_TABLE[0o174].unwrap().op, Asm8086::Jl);
+        assert_eq!(OPCODE_TABLE[0o053].unwrap().op, Asm8086::Sub);
+        assert_eq!(OPCODE_TABLE[0o151].unwrap().cpu, Cpu::I80186);
+        assert_eq!(OPCODE_TABLE[0o143].unwrap().cpu, Cpu::I80286);
+        assert!(mov.mod_rm && mov.immediate.is_empty());
+        // enter Dw, Db
+        let enter = OPCODE_TABLE[0o310].unwrap();
+        assert!(!enter.mod_rm);
+        assert_eq!(enter.immediate, [Immediate::FixedWord, Immediate::Byte]);
+        assert_eq!(OPCODE_TABLE[0o152].unwrap().immediate, [Immediate::Byte]);
+        assert_eq!(OPCODE_TABLE[0o240].unwrap().immediate, [Immediate::Address]);
+    }
+
+    #[test]
+    fn leaves_out_prefixes_and_groups() {
+        for opcode in [0o046, 0o360, 0o363, 0o146, 0o200, 0o366, 0o377, 0o330] {
+            assert_eq!(OPCODE_TABLE[opcode], None, "opcode {:#o}", opcode);
+        }
+        assert_eq!(OPCODE_TABLE[0o220].unwrap().op, Asm8086::Nop);
+    }
+}
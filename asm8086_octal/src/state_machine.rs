@@ -1,87 +1,187 @@
+//! Push based decoder for byte streams that arrive a byte at a time, e.g. from
+//! stdin, a socket or the fetch unit of the simulator.
+//!
+//! Bytes are kept only until they make up one instruction. Its length comes from
+//! `decoder::instruction_length_for` as soon as the prefixes, opcode and mod rm byte
+//! are in, and the instruction is decoded once, with the same tables as
+//! `decoder::decode_for`, when its last byte arrives.
 
+pub use crate::decoder::MAX_INSTRUCTION_LENGTH;
+use crate::decoder::{decode_at, instruction_length_for, DecodeError, DecodeErrorKind, Mode};
+use crate::instruction::DecodedInstruction;
 
-fn byte_octals(byte: u8) -> (u8, u8, u8) {
-    let first = (byte & 0b11000000) >> 6;
-    let second = (byte & 0b00111000) >> 3;
-    let third = byte & 0b00000111;
-    (first, second, third)
+/// What the decoder made of the bytes pushed so far.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// The bytes so far are the start of an instruction.
+    NeedMore,
+    /// The last byte completed an instruction, its offset counts from the start of the stream.
+    Instruction(DecodedInstruction),
+    /// The pending bytes are no instruction and have been dropped.
+    Error(DecodeError),
 }
 
-#[derive(Debug, Clone, Copy)]
-enum NextPart {
-    Opcode,
-    DestByteAddress,
-    DestWordAddress,
-    SrcByteAddress,
-    SrcWordAddress,
-    ByteDisp,
-    WordDisp,
+/// Decodes the bytes pushed into it one instruction at a time.
+///
+/// An unknown opcode is a `Step::Error` with its bytes, where `decode_for` gives
+/// an `Asm8086::Unknown` entry. Both skip the bytes and go on, but a stream has no
+/// listing to keep the bytes in, and the error step is how the fetch unit learns it
+/// has to raise an invalid opcode.
+#[derive(Debug)]
+pub struct StreamDecoder {
+    mode: Mode,
+    pending: Vec<u8>,
+    /// Length of the pending instruction once its first bytes tell it.
+    length: Option<usize>,
+    offset: usize,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum ParserState {
-    Byte(u8),
-    Word(u16),
-    NeedByteForAddress,
-    NeedByteDisp,
-    NeedWordDisp,
-    Done,
-    Error
-}
-
-fn parse_opcode(out: &mut String, byte: u8) -> (NextPart, ParserState) {
-    let (o1, o2, o3) = byte_octals(byte);
-    match (o1, o2) {
-       (0, 0) => out.push_str("add "),
-       (2, 1) => out.push_str("mov "),
-       _ => {}
-    };
-    match o3  {
-        0 => (NextPart::DestByteAddress, ParserState::NeedByteForAddress),
-        1 => (NextPart::DestWordAddress, ParserState::NeedByteForAddress),
-        2 => (NextPart::SrcByteAddress, ParserState::NeedByteForAddress),
-        3 => (NextPart::SrcWordAddress, ParserState::NeedByteForAddress),
-        _ => (NextPart::Opcode, ParserState::Error)
+impl StreamDecoder {
+    pub fn new(mode: Mode) -> Self {
+        StreamDecoder {
+            mode,
+            pending: Vec::with_capacity(MAX_INSTRUCTION_LENGTH),
+            length: None,
+            offset: 0,
+        }
     }
- 
-}
 
-fn state_machine(out: &mut String, part: NextPart, parsing: ParserState) -> (NextPart, ParserState) {
-    match (part, parsing) {
-        (NextPart::Opcode, ParserState::Byte(byte)) => {
-            parse_opcode(out, byte)
-       },
-        _ => (NextPart::Opcode, ParserState::Done)
+    /// Offset in the stream of the next instruction.
+    pub fn offset(&self) -> usize {
+        self.offset
     }
-}
 
-fn parse_bytes(bytes: &[u8]) {
-    let mut ptr = 0;
-    let mut part = NextPart::Opcode;
-    let mut parsing = ParserState::NeedByteForAddress;
-    let mut out = String::new();
-    loop {
-        let bytes_left = ptr != bytes.len();
-        parsing = match (bytes_left, parsing) {
-            (true, ParserState::NeedByteForAddress) => {
-                let byte = bytes[ptr];
-                ptr += 1;
-                ParserState::Byte(byte) 
+    pub fn push(&mut self, byte: u8) -> Step {
+        self.pending.push(byte);
+        let length = match self.length {
+            Some(length) => length,
+            None => match instruction_length_for(&self.pending, self.mode) {
+                Ok(length) => *self.length.insert(length),
+                // past `MAX_INSTRUCTION_LENGTH` bytes it says `TooLong` instead
+                Err(error) if error.kind == DecodeErrorKind::Truncated => return Step::NeedMore,
+                Err(error) => return Step::Error(self.fail(error.kind, error.bytes.len())),
             },
-            (_, _) => {
-                println!("Unable to parse symbol");
-                break
-            }
-            (false, ParserState::Done) => {
-                println!("Finished sucessully");
-                break
+        };
+        if self.pending.len() < length {
+            return Step::NeedMore;
+        }
+        match decode_at(&self.pending, 0, self.mode) {
+            Ok(mut instruction) => {
+                instruction.offset = self.offset;
+                self.consume(instruction.length);
+                Step::Instruction(instruction)
             }
-            (false, _) => {
-                println!("Finished with error");
-                break
+            Err(error) => Step::Error(self.fail(error.kind, error.bytes.len())),
+        }
+    }
+
+    /// Ends the stream, reporting the bytes of an instruction that never completed.
+    pub fn finish(&mut self) -> Option<DecodeError> {
+        match self.pending.len() {
+            0 => None,
+            length => Some(self.fail(DecodeErrorKind::Truncated, length)),
+        }
+    }
+
+    fn fail(&mut self, kind: DecodeErrorKind, length: usize) -> DecodeError {
+        let error = DecodeError {
+            kind,
+            offset: self.offset,
+            bytes: self.pending[..length].to_vec(),
+        };
+        self.consume(length);
+        error
+    }
+
+    fn consume(&mut self, length: usize) {
+        self.pending.drain(..length);
+        self.length = None;
+        self.offset += length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{decode_for, Bits, Cpu};
+
+    fn push_all(decoder: &mut StreamDecoder, bytes: &[u8]) -> Vec<Step> {
+        bytes.iter().map(|&byte| decoder.push(byte)).collect()
+    }
+
+    #[test]
+    fn can_decode_one_byte_at_a_time() {
+        let elf = crate::elf::Elf::parse(include_bytes!("../sum.o")).unwrap();
+        let inputs = [
+            (
+                &include_bytes!("../listing_0039_more_movs.bin")[..],
+                Mode::default(),
+            ),
+            (
+                elf.text().unwrap().bytes,
+                Mode::new(Cpu::I80386, Bits::SixtyFour),
+            ),
+        ];
+        for (bytes, mode) in inputs {
+            let mut decoder = StreamDecoder::new(mode);
+            let instructions: Vec<DecodedInstruction> = push_all(&mut decoder, bytes)
+                .into_iter()
+                .filter_map(|step| match step {
+                    Step::NeedMore => None,
+                    Step::Instruction(instruction) => Some(instruction),
+                    Step::Error(error) => panic!("{}", error),
+                })
+                .collect();
+            assert_eq!(decoder.finish(), None);
+            assert_eq!(instructions, decode_for(bytes, mode).unwrap());
+        }
+    }
+
+    #[test]
+    fn signals_need_more_until_instruction_is_complete() {
+        let mut decoder = StreamDecoder::new(Mode::default());
+        let steps = push_all(&mut decoder, &[0o307, 0o006, 0o020, 0o000, 0o001]);
+        assert!(steps.iter().all(|step| *step == Step::NeedMore));
+        match decoder.push(0o000) {
+            Step::Instruction(instruction) => {
+                assert_eq!(instruction.to_string(), "mov word [16], 1")
             }
-            };
+            step => panic!("{:?}", step),
         }
-        let new_state = state_machine(&mut out, part, parsing);
+        assert_eq!(decoder.offset(), 6);
+    }
+
+    #[test]
+    fn reports_errors_and_keeps_going() {
+        let mut decoder = StreamDecoder::new(Cpu::I80286.into());
+        let steps = push_all(&mut decoder, &[0o017, 0o017, 0o215, 0o300, 0o220, 0o307]);
+        let errors: Vec<(DecodeErrorKind, usize)> = steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Error(error) => Some((error.kind, error.offset)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (DecodeErrorKind::UnknownOpcode, 0),
+                (DecodeErrorKind::InvalidModRm, 2),
+            ]
+        );
+        assert!(matches!(&steps[4], Step::Instruction(nop) if nop.offset == 4));
+        let error = decoder.finish().unwrap();
+        assert_eq!((error.kind, error.offset), (DecodeErrorKind::Truncated, 5));
     }
 
+    #[test]
+    fn gives_up_on_endless_prefixes() {
+        let mut decoder = StreamDecoder::new(Mode::new(Cpu::I80386, Bits::ThirtyTwo));
+        let steps = push_all(&mut decoder, &[0o146; MAX_INSTRUCTION_LENGTH]);
+        match steps.last() {
+            Some(Step::Error(error)) => assert_eq!(error.kind, DecodeErrorKind::TooLong),
+            step => panic!("{:?}", step),
+        }
+        assert_eq!(decoder.finish(), None);
+    }
+}
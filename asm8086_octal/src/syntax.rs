@@ -0,0 +1,949 @@
+//! Assembler dialects a decoded instruction can be written in.
+//!
+//! NASM is what the `Display` impls of `instruction` print and what the course
+//! listings are written in, MASM differs in the size keywords and a few spellings,
+//! and AT&T (the GNU as and objdump default) reverses operands and marks every
+//! register and immediate.
+//!
+//! Given the bytes an instruction came from, NASM output keeps the exact encoding:
+//! `strict` and `near` where nasm would pick a shorter form, `db` where nasm can not
+//! be asked for the bytes at all.
+
+use std::fmt::Display;
+
+use crate::decoder::{byte_octals, decode_at, read_prefixes, Bits, Mode};
+use crate::instruction::{
+    Address, Asm8086, DecodedInstruction, Disp, DwordRegister, Operand, Operands, Pointer,
+    QwordRegister,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    #[default]
+    Nasm,
+    Masm,
+    Att,
+}
+
+impl std::str::FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "nasm" => Ok(Syntax::Nasm),
+            "masm" | "intel" => Ok(Syntax::Masm),
+            "att" | "gas" => Ok(Syntax::Att),
+            _ => Err(format!("Unknown syntax '{}'", text)),
+        }
+    }
+}
+
+impl Syntax {
+    /// Directive that puts the assembler into the word size the code was decoded with.
+    pub fn header(self, bits: Bits) -> Option<&'static str> {
+        match (self, bits) {
+            (Syntax::Nasm, Bits::Sixteen) => Some("bits 16"),
+            (Syntax::Nasm, Bits::ThirtyTwo) => Some("bits 32"),
+            (Syntax::Nasm, Bits::SixtyFour) => Some("bits 64"),
+            (Syntax::Att, Bits::Sixteen) => Some(".code16"),
+            (Syntax::Att, Bits::ThirtyTwo) => Some(".code32"),
+            (Syntax::Att, Bits::SixtyFour) => Some(".code64"),
+            (Syntax::Masm, _) => None,
+        }
+    }
+
+    /// Start of a comment running to the end of the line.
+    pub fn comment(self) -> &'static str {
+        match self {
+            Syntax::Nasm | Syntax::Masm => ";",
+            Syntax::Att => "#",
+        }
+    }
+
+    pub fn format(self, instruction: &DecodedInstruction) -> Formatted<'_> {
+        Formatted {
+            instruction,
+            syntax: self,
+            label: None,
+            encoding: None,
+        }
+    }
+}
+
+/// An instruction displayed in one syntax, see `Syntax::format`.
+pub struct Formatted<'a> {
+    instruction: &'a DecodedInstruction,
+    syntax: Syntax,
+    label: Option<String>,
+    encoding: Option<(&'a [u8], Mode)>,
+}
+
+impl<'a> Formatted<'a> {
+    /// Writes `label` instead of the relative form of the jump target.
+    pub fn with_label(mut self, label: Option<String>) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// Keeps the NASM output assembling back to `bytes`, the instruction's own bytes
+    /// decoded in `mode`. The other syntaxes are written as before.
+    pub fn with_encoding(mut self, bytes: &'a [u8], mode: Mode) -> Self {
+        self.encoding = Some((bytes, mode));
+        self
+    }
+
+    fn nasm_encoding(&self) -> Encoding {
+        match (self.syntax, self.encoding) {
+            (Syntax::Nasm, Some((bytes, mode))) => nasm_encoding(self.instruction, bytes, mode),
+            _ => Encoding::Canonical,
+        }
+    }
+}
+
+impl Display for Formatted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.syntax, self.nasm_encoding()) {
+            (Syntax::Att, _) => self.write_att(f),
+            (_, Encoding::Bytes) => self.write_bytes(f),
+            (_, encoding) => self.write_intel(f, encoding),
+        }
+    }
+}
+
+/// What nasm has to be told to pick the bytes an instruction was decoded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    /// nasm picks these bytes on its own.
+    Canonical,
+    /// The immediate needs `strict`, nasm would shorten it to a sign extended byte.
+    StrictImmediate,
+    /// The jump needs `near`, nasm would make it short.
+    NearJump,
+    /// nasm has no way to ask for these bytes, they are written with `db`.
+    Bytes,
+}
+
+fn nasm_encoding(instruction: &DecodedInstruction, bytes: &[u8], mode: Mode) -> Encoding {
+    use Encoding::*;
+    let Some((_, opcode_ptr)) = read_prefixes(bytes, 0, mode) else {
+        return Bytes;
+    };
+    if instruction.op == Asm8086::Unknown || !nasm_prefix_order(&bytes[..opcode_ptr]) {
+        return Bytes;
+    }
+    let Some(&opcode) = bytes.get(opcode_ptr) else {
+        return Bytes;
+    };
+    if has_silent_prefix(instruction, bytes, opcode_ptr, mode) {
+        return Bytes;
+    }
+    let two_byte = opcode == 0o017;
+    // meaningless for the opcodes without a mod/rm byte, the arms below never look
+    let mod_rm_ptr = opcode_ptr + 1 + usize::from(two_byte);
+    let (mod_bits, reg, rm) = bytes.get(mod_rm_ptr).map_or((0, 0, 0), |&b| byte_octals(b));
+    let registers = mod_bits == 3;
+    let long = mode.bits == Bits::SixtyFour;
+    let pointer = instruction
+        .operands
+        .iter()
+        .find_map(|operand| match operand.address() {
+            Some(Address::Pointer(pointer)) => Some(pointer),
+            _ => None,
+        });
+    if pointer.is_some_and(|pointer| !nasm_addressing(pointer, rm, long)) {
+        return Bytes;
+    }
+    let immediate = instruction
+        .operands
+        .iter()
+        .find_map(|operand| match operand {
+            Operand::D(disp) | Operand::Du(disp) => disp.value(),
+            _ => None,
+        });
+    let fits_byte = immediate.is_some_and(|value| i8::try_from(value).is_ok());
+    // nasm makes a jump short whenever the target is in reach of the two byte form
+    let short_jump = instruction.operands.iter().any(|operand| match operand {
+        Operand::C(disp) => disp
+            .value()
+            .is_some_and(|disp| i8::try_from(disp + instruction.length as isize - 2).is_ok()),
+        _ => false,
+    });
+    let direct = matches!(pointer, Some(Pointer::Direct(_)));
+    if two_byte {
+        return match bytes.get(opcode_ptr + 1) {
+            Some(0o200..=0o217) if short_jump => NearJump,
+            _ => Canonical,
+        };
+    }
+    match opcode {
+        // register to register with the direction bit set, nasm always clears it
+        0o000..=0o077 if opcode & 0o6 == 0o2 && registers => Bytes,
+        0o212 | 0o213 if registers => Bytes,
+        // the accumulator has shorter forms
+        0o210..=0o213 if direct && reg == 0 && !long => Bytes,
+        0o200 | 0o201 if registers && rm == 0 => Bytes,
+        0o366 | 0o367 if registers && reg == 0 && rm == 0 => Bytes,
+        0o207 if registers && (reg == 0 || rm == 0) => Bytes,
+        // so do the registers, with the register in the opcode
+        0o306 | 0o307 | 0o217 if registers => Bytes,
+        0o377 if registers && (reg == 6 || (reg <= 1 && !long)) => Bytes,
+        // aliases nasm writes with another opcode or group member
+        0o202 => Bytes,
+        0o366 | 0o367 if reg == 1 => Bytes,
+        0o300 | 0o301 | 0o320..=0o323 if reg == 6 => Bytes,
+        0o300 | 0o301 if immediate == Some(1) => Bytes,
+        0o005..=0o075 if opcode & 0o7 == 0o5 && fits_byte => StrictImmediate,
+        0o150 | 0o151 | 0o201 if fits_byte => StrictImmediate,
+        0o270..=0o277 if immediate.is_some_and(|value| i32::try_from(value).is_ok()) => {
+            match instruction.operands {
+                Operands::Two(Operand::Rq(_), _) => StrictImmediate,
+                _ => Canonical,
+            }
+        }
+        0o351 if short_jump => NearJump,
+        _ => Canonical,
+    }
+}
+
+/// nasm writes the prefixes in one order: repeat, lock, segment, operand size,
+/// address size and REX last. Any other order or a repeated prefix is lost.
+fn nasm_prefix_order(prefixes: &[u8]) -> bool {
+    let rank = |byte: u8| match byte {
+        0o362 | 0o363 => 0,
+        0o360 => 1,
+        0o046 | 0o056 | 0o066 | 0o076 | 0o144 | 0o145 => 2,
+        0o146 => 3,
+        0o147 => 4,
+        _ => 5,
+    };
+    prefixes
+        .windows(2)
+        .all(|pair| rank(pair[0]) < rank(pair[1]))
+}
+
+/// Whether the instruction decodes the same without one of its size prefixes or
+/// its REX prefix, which nasm only writes when the operands need them.
+fn has_silent_prefix(
+    instruction: &DecodedInstruction,
+    bytes: &[u8],
+    opcode_ptr: usize,
+    mode: Mode,
+) -> bool {
+    let long = mode.bits == Bits::SixtyFour;
+    (0..opcode_ptr)
+        .filter(|&at| matches!(bytes[at], 0o146 | 0o147) || (long && bytes[at] & 0o360 == 0o100))
+        .any(|at| {
+            let without = [&bytes[..at], &bytes[at + 1..]].concat();
+            decode_at(&without, 0, mode).is_ok_and(|other| {
+                (other.op, other.operands) == (instruction.op, instruction.operands)
+            })
+        })
+}
+
+/// Whether nasm encodes `pointer` with the same mod/rm and SIB bytes, given the
+/// displacement keyword of `displacement_keyword`. A SIB byte nasm would leave
+/// out and a lone scaled index nasm would split into base and index are not.
+fn nasm_addressing(pointer: Pointer, rm: u8, long: bool) -> bool {
+    match pointer {
+        Pointer::Indexed {
+            base, index: None, ..
+        } if rm == 4 => {
+            matches!(base, Some(DwordRegister::ESP | DwordRegister::R12D))
+                || (base.is_none() && long)
+        }
+        Pointer::Indexed64 {
+            base, index: None, ..
+        } if rm == 4 => {
+            matches!(base, Some(QwordRegister::RSP | QwordRegister::R12)) || base.is_none()
+        }
+        Pointer::Indexed {
+            base: None,
+            index: Some(_),
+            scale,
+            ..
+        }
+        | Pointer::Indexed64 {
+            base: None,
+            index: Some(_),
+            scale,
+            ..
+        } => scale > 2,
+        _ => true,
+    }
+}
+
+/// Keyword nasm needs inside the brackets to keep a displacement it would shorten
+/// or leave out, `[byte bx]` for a zero byte.
+fn displacement_keyword(pointer: Pointer) -> Option<&'static str> {
+    use Pointer::*;
+    let (disp, needs_disp) = match pointer {
+        BxSi(disp) | BxDi(disp) | BpSi(disp) | BpDi(disp) | Si(disp) | Di(disp) | Bx(disp) => {
+            (disp, false)
+        }
+        Bp(disp) => (disp, true),
+        Indexed {
+            base: Some(base),
+            disp,
+            ..
+        } => (
+            disp,
+            matches!(base, DwordRegister::EBP | DwordRegister::R13D),
+        ),
+        Indexed64 {
+            base: Some(base),
+            disp,
+            ..
+        } => (
+            disp,
+            matches!(base, QwordRegister::RBP | QwordRegister::R13),
+        ),
+        _ => return None,
+    };
+    let fits_byte = disp
+        .value()
+        .is_some_and(|value| i8::try_from(value).is_ok());
+    match disp {
+        Disp::D8(0) if !needs_disp => Some("byte"),
+        Disp::D16(_) if fits_byte => Some("word"),
+        Disp::D32(_) if fits_byte => Some("dword"),
+        _ => None,
+    }
+}
+
+/// Size keyword of a `strict` immediate.
+fn immediate_keyword(disp: Disp) -> &'static str {
+    match disp {
+        Disp::D8(_) => "byte",
+        Disp::D16(_) => "word",
+        Disp::D32(_) => "dword",
+        _ => "qword",
+    }
+}
+
+fn size_keyword(operand: Operand, syntax: Syntax) -> Option<&'static str> {
+    let masm = syntax == Syntax::Masm;
+    let keyword = match operand {
+        Operand::Eb(_) if masm => "byte ptr",
+        Operand::Ew(_) if masm => "word ptr",
+        Operand::Ef(_) if masm => "far ptr",
+        Operand::Ed(_) if masm => "dword ptr",
+        Operand::Eq(_) if masm => "qword ptr",
+        Operand::Et(_) if masm => "tbyte ptr",
+        Operand::Eb(_) => "byte",
+        Operand::Ew(_) => "word",
+        Operand::Ef(_) => "far",
+        Operand::Ed(_) => "dword",
+        Operand::Eq(_) => "qword",
+        Operand::Et(_) => "tword",
+        _ => return None,
+    };
+    Some(keyword)
+}
+
+impl Formatted<'_> {
+    /// Shared by the start of both layouts: a segment override without a memory
+    /// operand to attach to (string ops), `lock` and the repeat prefix.
+    fn write_prefixes(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let instruction = self.instruction;
+        let has_memory = instruction
+            .operands
+            .iter()
+            .any(|operand| operand.is_memory());
+        if let (Some(segment), false) = (instruction.prefixes.segment, has_memory) {
+            write!(f, "{} ", segment)?;
+        }
+        if instruction.prefixes.lock {
+            write!(f, "lock ")?;
+        }
+        if let Some(repeat) = instruction.repeat_text() {
+            write!(f, "{} ", repeat)?;
+        }
+        Ok(())
+    }
+
+    /// `db` with the instruction's bytes and the instruction itself as a comment.
+    fn write_bytes(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes = self.encoding.map_or(&[][..], |(bytes, _)| bytes);
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#o}", byte)).collect();
+        let text = Syntax::Nasm
+            .format(self.instruction)
+            .with_label(self.label.clone());
+        write!(f, "db {} ; {}", bytes.join(", "), text)
+    }
+
+    fn write_intel(&self, f: &mut std::fmt::Formatter<'_>, encoding: Encoding) -> std::fmt::Result {
+        let instruction = self.instruction;
+        let masm = self.syntax == Syntax::Masm;
+        self.write_prefixes(f)?;
+        write!(f, "{}", instruction.op)?;
+        let needs_size = instruction.needs_size();
+        for (idx, operand) in instruction.operands.iter().enumerate() {
+            let separator = if idx == 0 { " " } else { ", " };
+            write!(f, "{}", separator)?;
+            let pointer = match operand.address() {
+                Some(Address::Pointer(pointer)) => pointer,
+                _ => {
+                    self.write_intel_operand(f, operand, encoding)?;
+                    continue;
+                }
+            };
+            if let (Some(keyword), true) = (size_keyword(operand, self.syntax), needs_size) {
+                write!(f, "{} ", keyword)?;
+            }
+            if masm {
+                match instruction.prefixes.segment {
+                    Some(segment) => write!(f, "{}:", segment)?,
+                    // masm reads a bare [16] as the number 16
+                    None if matches!(pointer, Pointer::Direct(_)) => write!(f, "ds:")?,
+                    None => {}
+                }
+                write!(f, "{}", operand)?;
+                continue;
+            }
+            // nasm wants the segment and the displacement size inside the brackets
+            write!(f, "[")?;
+            if let Some(segment) = instruction.prefixes.segment {
+                write!(f, "{}:", segment)?;
+            }
+            if let (Some(keyword), Some(_)) = (displacement_keyword(pointer), self.encoding) {
+                write!(f, "{} ", keyword)?;
+            }
+            write!(f, "{}", pointer.to_string().trim_start_matches('['))?;
+        }
+        Ok(())
+    }
+
+    fn write_intel_operand(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        operand: Operand,
+        encoding: Encoding,
+    ) -> std::fmt::Result {
+        let masm = self.syntax == Syntax::Masm;
+        match (operand, encoding) {
+            (Operand::C(_), Encoding::NearJump) => write!(f, "near ")?,
+            (Operand::D(disp), Encoding::StrictImmediate) => {
+                write!(f, "strict {} ", immediate_keyword(disp))?
+            }
+            _ => {}
+        }
+        if let (Operand::C(_), Some(label)) = (operand, &self.label) {
+            return write!(f, "{}", label);
+        }
+        match operand {
+            // relative to the start of this instruction, the way nasm and masm read `$`
+            Operand::C(disp) => {
+                let relative = disp.value().unwrap_or(0) + self.instruction.length as isize;
+                write!(f, "${:+}", relative)
+            }
+            Operand::St(i) if masm => write!(f, "st({})", i),
+            Operand::Data(byte) if masm => write!(f, "{:o}o", byte),
+            _ => write!(f, "{}", operand),
+        }
+    }
+
+    fn write_att(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let instruction = self.instruction;
+        self.write_prefixes(f)?;
+        write!(f, "{}", att_mnemonic(instruction))?;
+        let mut operands: Vec<Operand> = instruction.operands.iter().collect();
+        // enter and bound keep the intel order in gas
+        if !matches!(instruction.op, Asm8086::Enter | Asm8086::Bound) {
+            operands.reverse();
+        }
+        let indirect = matches!(instruction.op, Asm8086::Call | Asm8086::Jmp);
+        for (idx, operand) in operands.into_iter().enumerate() {
+            let separator = if idx == 0 { " " } else { ", " };
+            write!(f, "{}", separator)?;
+            if indirect && operand.address().is_some() {
+                write!(f, "*")?;
+            }
+            self.write_att_operand(f, operand)?;
+        }
+        Ok(())
+    }
+
+    fn write_att_operand(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        operand: Operand,
+    ) -> std::fmt::Result {
+        if let (Operand::C(_), Some(label)) = (operand, &self.label) {
+            return write!(f, "{}", label);
+        }
+        match operand {
+            // gas reads `.` as the start of this instruction
+            Operand::C(disp) => {
+                let relative = disp.value().unwrap_or(0) + self.instruction.length as isize;
+                write!(f, ".{:+}", relative)
+            }
+            Operand::D(_) | Operand::Du(_) => write!(f, "${}", operand),
+            Operand::Af(offset, segment) => {
+                let offset = offset.value().unwrap_or(0) as u32;
+                let segment = segment.value().unwrap_or(0) as u16;
+                write!(f, "${}, ${}", segment, offset)
+            }
+            Operand::St(i) => write!(f, "%st({})", i),
+            Operand::Data(byte) => write!(f, "0{:o}", byte),
+            _ => match operand.address() {
+                Some(Address::Pointer(pointer)) => {
+                    if let Some(segment) = self.instruction.prefixes.segment {
+                        write!(f, "%{}:", segment)?;
+                    }
+                    write_att_pointer(f, pointer)
+                }
+                Some(address) => write!(f, "%{}", address),
+                None => write!(f, "{}", operand),
+            },
+        }
+    }
+}
+
+/// `disp(base,index,scale)` with the parts that are absent left out.
+fn write_att_pointer(f: &mut std::fmt::Formatter<'_>, pointer: Pointer) -> std::fmt::Result {
+    // a plain number is an absolute address, the intel form without brackets
+    if let Pointer::Direct(_) | Pointer::Unread = pointer {
+        let intel = pointer.to_string();
+        return write!(f, "{}", intel.trim_start_matches('[').trim_end_matches(']'));
+    }
+    let (base, index, scale, disp) = pointer.parts();
+    match disp.value() {
+        Some(0) | None if base.is_some() || index.is_some() => {}
+        Some(value) => write!(f, "{}", value)?,
+        None => write!(f, "0")?,
+    }
+    if base.is_none() && index.is_none() {
+        return Ok(());
+    }
+    write!(f, "(")?;
+    if let Some(base) = base {
+        write!(f, "%{}", base)?;
+    }
+    if let Some(index) = index {
+        write!(f, ",%{}", index)?;
+        if scale > 1 {
+            write!(f, ",{}", scale)?;
+        }
+    }
+    write!(f, ")")
+}
+
+/// Size letter of a register operand for the zero and sign extensions.
+fn att_register_suffix(operand: Operand) -> &'static str {
+    match operand.address() {
+        Some(Address::ByteRegister(_)) => "b",
+        Some(Address::WordRegister(_)) => "w",
+        Some(Address::DwordRegister(_)) => "l",
+        Some(Address::QwordRegister(_)) => "q",
+        _ => match operand {
+            Operand::Eb(_) => "b",
+            _ => "w",
+        },
+    }
+}
+
+/// Size letter gas wants when no register operand gives the size away.
+/// x87 memory operands spell theirs differently for integers and reals.
+fn att_memory_suffix(instruction: &DecodedInstruction) -> &'static str {
+    use Asm8086::*;
+    if !instruction.needs_size() {
+        return "";
+    }
+    let integer = matches!(
+        instruction.op,
+        Fiadd | Fimul | Ficom | Ficomp | Fisub | Fisubr | Fidiv | Fidivr | Fild | Fist | Fistp
+    );
+    let real = matches!(
+        instruction.op,
+        Fadd | Fmul | Fcom | Fcomp | Fsub | Fsubr | Fdiv | Fdivr | Fld | Fst | Fstp
+    );
+    let memory = instruction
+        .operands
+        .iter()
+        .find(|operand| operand.is_memory());
+    match memory {
+        Some(Operand::Eb(_)) => "b",
+        Some(Operand::Ew(_)) if integer => "s",
+        Some(Operand::Ew(_)) if !matches!(instruction.op, Fldcw | Fnstcw | Fnstsw) => "w",
+        Some(Operand::Ed(_)) if real => "s",
+        Some(Operand::Ed(_)) => "l",
+        Some(Operand::Eq(_)) if integer => "ll",
+        Some(Operand::Eq(_)) if real => "l",
+        Some(Operand::Eq(_)) => "q",
+        Some(Operand::Et(_)) if real => "t",
+        _ => "",
+    }
+}
+
+fn att_mnemonic(instruction: &DecodedInstruction) -> String {
+    use Asm8086::*;
+    let far = matches!(
+        instruction.operands,
+        Operands::One(Operand::Ef(_) | Operand::Af(..))
+    );
+    // gas still swaps the reversed forms when st(i) is the destination
+    let st_destination = matches!(
+        instruction.operands,
+        Operands::Two(Operand::St(i), Operand::St(0)) if i != 0
+    );
+    let text = match instruction.op {
+        Call if far => "lcall",
+        Jmp if far => "ljmp",
+        Retf => "lret",
+        Cbw => "cbtw",
+        Cwd => "cwtd",
+        Cwde => "cwtl",
+        Cdq => "cltd",
+        Cdqe => "cltq",
+        Cqo => "cqto",
+        Movsd => "movsl",
+        Cmpsd => "cmpsl",
+        Stosd => "stosl",
+        Lodsd => "lodsl",
+        Scasd => "scasl",
+        Insd => "insl",
+        Outsd => "outsl",
+        Pushad => "pushal",
+        Popad => "popal",
+        Pushfd => "pushfl",
+        Popfd => "popfl",
+        Iretd => "iretl",
+        Movsxd => "movslq",
+        Db => ".byte",
+        Fsub if st_destination => "fsubr",
+        Fsubr if st_destination => "fsub",
+        Fdiv if st_destination => "fdivr",
+        Fdivr if st_destination => "fdiv",
+        Fsubp => "fsubrp",
+        Fsubrp => "fsubp",
+        Fdivp => "fdivrp",
+        Fdivrp => "fdivp",
+        Movzx | Movsx => {
+            let (target, source) = match instruction.operands {
+                Operands::Two(target, source) => (target, source),
+                _ => return instruction.op.to_string(),
+            };
+            let stem = if instruction.op == Movzx {
+                "movz"
+            } else {
+                "movs"
+            };
+            return format!(
+                "{}{}{}",
+                stem,
+                att_register_suffix(source),
+                att_register_suffix(target)
+            );
+        }
+        op => return format!("{}{}", op, att_memory_suffix(instruction)),
+    };
+    text.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::decoder::{decode, decode_for, Cpu};
+    use crate::labels::Labels;
+
+    fn disassemble(bytes: &[u8], mode: Mode, syntax: Syntax) -> Vec<String> {
+        decode_for(bytes, mode)
+            .unwrap()
+            .iter()
+            .map(|instruction| syntax.format(instruction).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn can_parse_syntax_names() {
+        assert_eq!("nasm".parse(), Ok(Syntax::Nasm));
+        assert_eq!("masm".parse(), Ok(Syntax::Masm));
+        assert_eq!("att".parse(), Ok(Syntax::Att));
+        assert!("tasm".parse::<Syntax>().is_err());
+    }
+
+    #[test]
+    fn nasm_matches_display() {
+        let instructions = decode(include_bytes!("../listing_0039_more_movs.bin")).unwrap();
+        for instruction in instructions.iter() {
+            assert_eq!(
+                Syntax::Nasm.format(instruction).to_string(),
+                instruction.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn can_write_masm_size_keywords() {
+        // mov word [bx + si + 4], 7 / mov byte [16], 1 / fld st2
+        let bytes = [
+            0o307, 0o100, 0o004, 0o007, 0o000, 0o306, 0o006, 0o020, 0o000, 0o001, 0o331, 0o302,
+        ];
+        let lines = disassemble(&bytes, Mode::default(), Syntax::Masm);
+        assert_eq!(
+            lines,
+            vec![
+                "mov word ptr [bx + si + 4], 7",
+                "mov byte ptr ds:[16], 1",
+                "fld st(2)"
+            ]
+        );
+    }
+
+    #[test]
+    fn can_write_att_operands() {
+        // mov [bp + di - 37], cl / mov ax, [bx + si + 4] / add word [es:bx], 5
+        // jmp [bx] / jmp far 0x1234:0x5678 / rep stosb
+        let bytes = [
+            0o210, 0o113, 0o333, 0o213, 0o100, 0o004, 0o046, 0o203, 0o007, 0o005, 0o377, 0o047,
+            0o352, 0o170, 0o126, 0o064, 0o022, 0o363, 0o252,
+        ];
+        let lines = disassemble(&bytes, Mode::default(), Syntax::Att);
+        assert_eq!(
+            lines,
+            vec![
+                "mov %cl, -37(%bp,%di)",
+                "mov 4(%bx,%si), %ax",
+                "addw $5, %es:(%bx)",
+                "jmpw *(%bx)",
+                "ljmp $4660, $22136",
+                "rep stosb"
+            ]
+        );
+    }
+
+    #[test]
+    fn can_write_att_for_x86_64() {
+        // mov eax, [rip + 16] / movsxd rax, dword [rbx + rcx*8 - 8] / cdqe
+        let bytes = [
+            0x8b, 0x05, 0x10, 0x00, 0x00, 0x00, 0x48, 0x63, 0x44, 0xcb, 0xf8, 0x48, 0x98,
+        ];
+        let mode = Mode::new(Cpu::I80386, Bits::SixtyFour);
+        let lines = disassemble(&bytes, mode, Syntax::Att);
+        assert_eq!(
+            lines,
+            vec!["mov 16(%rip), %eax", "movslq -8(%rbx,%rcx,8), %rax", "cltq"]
+        );
+    }
+
+    fn nasm_source(bytes: &[u8], mode: Mode) -> String {
+        let instructions = decode_for(bytes, mode).unwrap();
+        let labels = Labels::collect(&instructions);
+        let mut lines = vec![Syntax::Nasm.header(mode.bits).unwrap().to_string()];
+        for instruction in instructions.iter() {
+            if let Some(label) = labels.name(instruction.offset) {
+                lines.push(format!("{}:", label));
+            }
+            let code = &bytes[instruction.offset..instruction.offset + instruction.length];
+            let text = labels.apply(instruction).with_encoding(code, mode);
+            lines.push(text.to_string());
+        }
+        if let Some(label) = labels.name(bytes.len()) {
+            lines.push(format!("{}:", label));
+        }
+        lines.join("\n")
+    }
+
+    // mov bx, cx through the direction bit / add bl, 5 through 0o202 /
+    // add bx, 5 with a word immediate / jmp +2 with a word displacement /
+    // mov ax, [bx] with a zero byte displacement / mov al, [16] without the
+    // accumulator form / rep es movsb with the prefixes swapped
+    const NON_CANONICAL: [u8; 26] = [
+        0o213, 0o331, 0o202, 0o303, 0o005, 0o201, 0o303, 0o005, 0o000, 0o351, 0o002, 0o000, 0o213,
+        0o107, 0o000, 0o212, 0o006, 0o020, 0o000, 0o046, 0o363, 0o244, 0o220, 0o220, 0o220, 0o220,
+    ];
+
+    #[test]
+    fn can_keep_nasm_encodings() {
+        let source = nasm_source(&NON_CANONICAL, Mode::default());
+        let lines: Vec<&str> = source.lines().collect();
+        assert_eq!(
+            lines[1..9],
+            [
+                "db 0o213, 0o331 ; mov bx, cx",
+                "db 0o202, 0o303, 0o5 ; add bl, 5",
+                "add bx, strict word 5",
+                "jmp near $+5",
+                "mov ax, [byte bx]",
+                "db 0o212, 0o6, 0o20, 0o0 ; mov al, [16]",
+                "db 0o46, 0o363, 0o244 ; es rep movsb",
+                "nop"
+            ]
+        );
+        // without the bytes the text stays the plain one
+        let instructions = decode(&NON_CANONICAL).unwrap();
+        assert_eq!(instructions[0].to_string(), "mov bx, cx");
+    }
+
+    /// One instruction each, with the nasm line that gives back its bytes.
+    const ENCODINGS: [(Bits, &[u8], &str); 31] = [
+        // immediates nasm would shorten to a sign extended byte
+        (Bits::Sixteen, &[0x05, 5, 0], "add ax, strict word 5"),
+        (
+            Bits::Sixteen,
+            &[0x81, 0xc3, 0xfb, 0xff],
+            "add bx, strict word -5",
+        ),
+        (Bits::Sixteen, &[0x81, 0xc3, 0x80, 0], "add bx, 128"),
+        (Bits::Sixteen, &[0x68, 5, 0], "push strict word 5"),
+        (
+            Bits::Sixteen,
+            &[0x69, 0xc3, 5, 0],
+            "imul ax, bx, strict word 5",
+        ),
+        (
+            Bits::Sixteen,
+            &[0x66, 0x05, 5, 0, 0, 0],
+            "add eax, strict dword 5",
+        ),
+        (
+            Bits::ThirtyTwo,
+            &[0x05, 5, 0, 0, 0],
+            "add eax, strict dword 5",
+        ),
+        (
+            Bits::SixtyFour,
+            &[0x48, 0x05, 5, 0, 0, 0],
+            "add rax, strict dword 5",
+        ),
+        (
+            Bits::SixtyFour,
+            &[0x48, 0xb8, 5, 0, 0, 0, 0, 0, 0, 0],
+            "mov rax, strict qword 5",
+        ),
+        (
+            Bits::SixtyFour,
+            &[0x48, 0xb8, 5, 0, 0, 0, 1, 0, 0, 0],
+            "mov rax, 4294967301",
+        ),
+        // jumps nasm would make short
+        (Bits::Sixteen, &[0xe9, 2, 0], "jmp near $+5"),
+        (Bits::Sixteen, &[0x0f, 0x84, 0, 0], "je near $+4"),
+        (Bits::Sixteen, &[0x0f, 0x84, 0, 1], "je $+260"),
+        (Bits::ThirtyTwo, &[0xe9, 0, 0, 0, 0], "jmp near $+5"),
+        (Bits::SixtyFour, &[0x0f, 0x85, 0, 0, 0, 0], "jne near $+6"),
+        // displacements nasm would shorten or leave out
+        (Bits::Sixteen, &[0x8b, 0x47, 0], "mov ax, [byte bx]"),
+        (Bits::Sixteen, &[0x8b, 0x87, 5, 0], "mov ax, [word bx + 5]"),
+        (Bits::Sixteen, &[0x8b, 0x46, 0], "mov ax, [bp]"),
+        (
+            Bits::ThirtyTwo,
+            &[0x8b, 0x80, 5, 0, 0, 0],
+            "mov eax, [dword eax + 5]",
+        ),
+        (
+            Bits::ThirtyTwo,
+            &[0x8b, 0x04, 0x85, 0, 0, 0, 0],
+            "mov eax, [eax*4]",
+        ),
+        (
+            Bits::SixtyFour,
+            &[0x48, 0x8b, 0x84, 0x24, 0x10, 0, 0, 0],
+            "mov rax, [dword rsp + 16]",
+        ),
+        (
+            Bits::SixtyFour,
+            &[0x8b, 0x04, 0x25, 0x10, 0, 0, 0],
+            "mov eax, [16]",
+        ),
+        // prefixes in nasm's order, the others only as bytes
+        (Bits::Sixteen, &[0xf3, 0x26, 0xa4], "es rep movsb"),
+        (
+            Bits::ThirtyTwo,
+            &[0x64, 0x66, 0x8b, 0x00],
+            "mov ax, [fs:eax]",
+        ),
+        (
+            Bits::ThirtyTwo,
+            &[0x66, 0x64, 0x8b, 0x00],
+            "db 0o146, 0o144, 0o213, 0o0 ; mov ax, [fs:eax]",
+        ),
+        // prefixes the operands do not need
+        (
+            Bits::ThirtyTwo,
+            &[0x66, 0x8a, 0x00],
+            "db 0o146, 0o212, 0o0 ; mov al, [eax]",
+        ),
+        (
+            Bits::SixtyFour,
+            &[0x66, 0x48, 0x8b, 0x00],
+            "db 0o146, 0o110, 0o213, 0o0 ; mov rax, [rax]",
+        ),
+        (Bits::SixtyFour, &[0x48, 0x90], "db 0o110, 0o220 ; nop"),
+        // SIB bytes nasm would leave out or fill in differently
+        (
+            Bits::ThirtyTwo,
+            &[0x8b, 0x04, 0x20],
+            "db 0o213, 0o4, 0o40 ; mov eax, [eax]",
+        ),
+        (
+            Bits::ThirtyTwo,
+            &[0x8b, 0x04, 0x45, 0, 0, 0, 0],
+            "db 0o213, 0o4, 0o105, 0o0, 0o0, 0o0, 0o0 ; mov eax, [eax*2]",
+        ),
+        (
+            Bits::SixtyFour,
+            &[0x8b, 0x04, 0x20],
+            "db 0o213, 0o4, 0o40 ; mov eax, [rax]",
+        ),
+    ];
+
+    #[test]
+    fn can_pick_nasm_encodings_in_every_mode() {
+        for (bits, bytes, expected) in ENCODINGS {
+            let mode = Mode::new(Cpu::I80386, bits);
+            let instruction = decode_for(bytes, mode).unwrap()[0];
+            assert_eq!(instruction.length, bytes.len(), "{}", expected);
+            let text = Syntax::Nasm.format(&instruction).with_encoding(bytes, mode);
+            assert_eq!(text.to_string(), expected);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs nasm"]
+    fn nasm_reassembles_byte_for_byte() {
+        let listings: [(&str, &[u8]); 5] = [
+            ("0039", include_bytes!("../listing_0039_more_movs.bin")),
+            ("0040", include_bytes!("../listing_0040_challenge_movs.bin")),
+            (
+                "0041",
+                include_bytes!("../listing_0041_add_sub_cmp_jnz.bin"),
+            ),
+            ("multiple_mov", include_bytes!("../multiple_mov.bin")),
+            ("non_canonical", &NON_CANONICAL),
+        ];
+        let listings = listings
+            .into_iter()
+            .map(|(name, bytes)| (name.to_string(), bytes, Mode::default()));
+        let encodings = ENCODINGS
+            .into_iter()
+            .enumerate()
+            .map(|(n, (bits, bytes, _))| {
+                let mode = Mode::new(Cpu::I80386, bits);
+                (format!("encoding_{}", n), bytes, mode)
+            });
+        let dir = std::env::temp_dir();
+        for (name, bytes, mode) in listings.chain(encodings) {
+            let source = dir.join(format!("asm8086_octal_{}.asm", name));
+            let output = dir.join(format!("asm8086_octal_{}.bin", name));
+            std::fs::write(&source, nasm_source(bytes, mode)).unwrap();
+            let status = Command::new("nasm")
+                .args(["-f", "bin", "-o"])
+                .arg(&output)
+                .arg(&source)
+                .status()
+                .expect("running nasm");
+            assert!(status.success(), "nasm rejected {}", name);
+            assert_eq!(std::fs::read(&output).unwrap(), bytes, "{}", name);
+        }
+    }
+
+    #[test]
+    fn can_write_headers() {
+        assert_eq!(Syntax::Nasm.header(Bits::Sixteen), Some("bits 16"));
+        assert_eq!(Syntax::Att.header(Bits::SixtyFour), Some(".code64"));
+        assert_eq!(Syntax::Masm.header(Bits::Sixteen), None);
+    }
+}
@@ -0,0 +1,9 @@
+int main() {
+    int sum = 0;
+    int size = 1024;
+    for (int i = 0; i < size; i++)
+    {
+        sum += i;
+    }
+    return 0;
+}
\ No newline at end of file